@@ -0,0 +1,118 @@
+use std::fs::File;
+use std::io::{Cursor, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use system::error::{Error, Result};
+use zip::{ZipArchive, ZipWriter, result::ZipError, write::SimpleFileOptions};
+
+/// Bounds on what extracting an uploaded archive may write.
+#[derive(Clone, Copy)]
+pub struct Limits {
+    /// Total uncompressed size of all entries, in bytes.
+    pub bytes: u64,
+    /// Number of entries, directories included.
+    pub files: usize,
+}
+
+pub struct Archive;
+
+impl Archive {
+    /// Checks that `bytes` is a non-empty zip whose listed contents fit in `limits`.
+    pub fn is_valid(bytes: &[u8], limits: Limits) -> Result<()> {
+        let mut archive = ZipArchive::new(Cursor::new(bytes)).map_err(Self::invalid)?;
+        if archive.is_empty() {
+            return Err(Error::InvalidArchive("archive is empty".to_string()));
+        }
+        Self::check_count(archive.len(), limits)?;
+        let mut total = 0u64;
+        for index in 0..archive.len() {
+            let entry = archive.by_index_raw(index).map_err(Self::invalid)?;
+            total = total.saturating_add(entry.size());
+            Self::check_size(total, limits)?;
+        }
+        Ok(())
+    }
+
+    fn check_count(count: usize, limits: Limits) -> Result<()> {
+        match count > limits.files {
+            true => Err(Error::InvalidArchive(format!(
+                "archive has {count} entries, more than the limit of {}",
+                limits.files
+            ))),
+            false => Ok(()),
+        }
+    }
+
+    fn check_size(total: u64, limits: Limits) -> Result<()> {
+        match total > limits.bytes {
+            true => Err(Error::InvalidArchive(format!(
+                "archive expands to more than the limit of {} bytes",
+                limits.bytes
+            ))),
+            false => Ok(()),
+        }
+    }
+
+    fn invalid(error: ZipError) -> Error {
+        match error {
+            ZipError::Io(e) => Error::Io(e),
+            e => Error::InvalidArchive(e.to_string()),
+        }
+    }
+
+    /// Extracts `bytes` under `path`, stopping as soon as the entries written
+    /// exceed `limits`, whatever sizes the archive claims.
+    pub fn extract(bytes: &[u8], path: &Path, limits: Limits) -> Result<Vec<PathBuf>> {
+        let mut archive = ZipArchive::new(Cursor::new(bytes)).map_err(Self::invalid)?;
+        Self::check_count(archive.len(), limits)?;
+        let mut files = Vec::new();
+        let mut total = 0u64;
+
+        for index in 0..archive.len() {
+            let mut entry = archive.by_index(index).map_err(Self::invalid)?;
+            let Some(name) = entry.enclosed_name() else {
+                let name = entry.name();
+                return Err(Error::InvalidArchive(format!("invalid path: {name}")));
+            };
+            let output_path = path.join(&name);
+            if entry.is_dir() {
+                std::fs::create_dir_all(&output_path)?;
+                continue;
+            }
+            if let Some(parent) = output_path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let remaining = limits.bytes.saturating_sub(total);
+            let mut file = File::create(&output_path)?;
+            let written = std::io::copy(
+                &mut (&mut entry).take(remaining.saturating_add(1)),
+                &mut file,
+            )?;
+            total += written;
+            Self::check_size(total, limits)?;
+            files.push(name);
+        }
+        Ok(files)
+    }
+
+    pub fn create<W: Write + Seek>(path: &Path, output: W) -> Result<W> {
+        let mut writer = ZipWriter::new(output);
+        let options = SimpleFileOptions::default();
+
+        let mut directories = vec![path.to_path_buf()];
+        while let Some(directory) = directories.pop() {
+            for entry in std::fs::read_dir(&directory)? {
+                let entry_path = entry?.path();
+                if entry_path.is_dir() {
+                    directories.push(entry_path);
+                    continue;
+                }
+                let name = entry_path.strip_prefix(path).unwrap_or(&entry_path);
+                let name = name.to_string_lossy().replace("\\", "/");
+                writer.start_file(name, options).map_err(Self::invalid)?;
+                std::io::copy(&mut File::open(&entry_path)?, &mut writer)?;
+            }
+        }
+        writer.finish().map_err(Self::invalid)
+    }
+}
@@ -1,11 +1,12 @@
-use std::{
-    path::{Path, PathBuf},
-    process::Command,
-};
+use std::path::{Path, PathBuf};
 
-use anyhow::{Result, bail};
 use ttf_parser::Face;
 
+use async_trait::async_trait;
+use system::error::{Error, Result};
+use system::tool::{self, Observer};
+use tokio::process::Command;
+
 use crate::process::Process;
 
 pub struct Font;
@@ -14,23 +15,30 @@ impl Font {
     pub fn is_valid(bytes: &[u8]) -> Result<()> {
         match Face::parse(bytes, 0) {
             Ok(_) => Ok(()),
-            Err(_) => bail!("Invalid font."),
+            Err(e) => Err(Error::InvalidFont(e.to_string())),
         }
     }
 }
 
+#[async_trait]
 impl Process for Font {
-    fn process(&self, path: &Path, file_name: &Path) -> Result<PathBuf> {
+    async fn process(
+        &self,
+        path: &Path,
+        file_name: &Path,
+        observer: &dyn Observer,
+    ) -> Result<PathBuf> {
         let program = system::programs::get_binary("mkbcfnt");
         let output_path = path.join(file_name).with_extension("bcfnt");
 
-        Command::new(program)
+        let mut command = Command::new(program);
+        command
             .arg(path.join(file_name))
             .arg("-o")
-            .arg(&output_path)
-            .output()?;
+            .arg(&output_path);
+        tool::run(&mut command, observer).await?;
 
-        std::fs::remove_file(path.join(file_name))?;
+        tokio::fs::remove_file(path.join(file_name)).await?;
         Ok(output_path.to_owned())
     }
 }
@@ -1,8 +1,8 @@
 use std::path::Path;
 
-use anyhow::Result;
 use image::{DynamicImage, ImageFormat};
 
+use system::error::{Error, Result};
 use system::platform::Platform;
 
 pub struct Icon {
@@ -11,19 +11,26 @@ pub struct Icon {
 }
 
 impl Icon {
-    pub fn from_bytes(target: &Platform, bytes: &[u8]) -> Option<Self> {
-        let mut image = image::load_from_memory(bytes).ok()?;
+    pub fn is_valid(bytes: &[u8]) -> Result<()> {
+        image::load_from_memory(bytes).map_err(|e| Error::InvalidIcon(e.to_string()))?;
+        Ok(())
+    }
+
+    pub fn from_bytes(target: &Platform, bytes: &[u8]) -> Result<Self> {
+        let mut image =
+            image::load_from_memory(bytes).map_err(|e| Error::InvalidIcon(e.to_string()))?;
         let ((width, height), format) = match target {
             Platform::Ctr => ((48, 48), ImageFormat::Png),
             Platform::Hac => ((256, 256), ImageFormat::Jpeg),
             Platform::Cafe => ((128, 128), ImageFormat::Png),
         };
         image = image.thumbnail(width, height);
-        Some(Self { image, format })
+        Ok(Self { image, format })
     }
 
     pub fn create(&self, path: &Path) -> Result<()> {
-        self.image.save_with_format(path, self.format)?;
-        Ok(())
+        self.image
+            .save_with_format(path, self.format)
+            .map_err(|e| Error::InvalidIcon(e.to_string()))
     }
 }
@@ -1,10 +1,13 @@
 use std::path::Path;
-use std::process::Command;
 use std::{io::Cursor, path::PathBuf};
 
-use anyhow::{Result, bail};
 use image::{DynamicImage, GenericImageView, ImageFormat, ImageReader};
 
+use async_trait::async_trait;
+use system::error::{Error, Result};
+use system::tool::{self, Observer};
+use tokio::process::Command;
+
 use crate::process::Process;
 
 pub struct Image;
@@ -19,30 +22,41 @@ impl Image {
         let reader = ImageReader::new(Cursor::new(bytes)).with_guessed_format()?;
         match reader.format() {
             Some(ImageFormat::Png | ImageFormat::Jpeg) => {
-                let image = reader.decode()?;
+                let image = reader
+                    .decode()
+                    .map_err(|e| Error::InvalidImage(e.to_string()))?;
                 if Self::validate(&image) {
                     return Ok(());
                 }
-                bail!("Invalid image.")
+                Err(Error::InvalidImage(
+                    "dimensions must be between 3 and 1024 pixels".to_string(),
+                ))
             }
-            _ => bail!("Failed to read image."),
+            _ => Err(Error::InvalidImage("expected a PNG or JPEG".to_string())),
         }
     }
 }
 
+#[async_trait]
 impl Process for Image {
-    fn process(&self, path: &Path, file_name: &Path) -> Result<PathBuf> {
+    async fn process(
+        &self,
+        path: &Path,
+        file_name: &Path,
+        observer: &dyn Observer,
+    ) -> Result<PathBuf> {
         let program = system::programs::get_binary("tex3ds");
         let output_path = path.join(file_name).with_extension("t3x");
 
-        Command::new(program)
+        let mut command = Command::new(program);
+        command
             .args(["-f", "rgba"])
             .arg(path.join(file_name))
             .arg("-o")
-            .arg(&output_path)
-            .output()?;
+            .arg(&output_path);
+        tool::run(&mut command, observer).await?;
 
-        std::fs::remove_file(path.join(file_name))?;
+        tokio::fs::remove_file(path.join(file_name)).await?;
         Ok(output_path.to_owned())
     }
 }
@@ -1,3 +1,4 @@
+pub mod archive;
 pub mod font;
 pub mod icon;
 pub mod image;
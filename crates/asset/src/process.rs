@@ -1,7 +1,15 @@
 use std::path::{Path, PathBuf};
 
-use anyhow::Result;
+use async_trait::async_trait;
+use system::error::Result;
+use system::tool::Observer;
 
-pub trait Process {
-    fn process(&self, path: &Path, file_name: &Path) -> Result<PathBuf>;
+#[async_trait]
+pub trait Process: Send + Sync {
+    async fn process(
+        &self,
+        path: &Path,
+        file_name: &Path,
+        observer: &dyn Observer,
+    ) -> Result<PathBuf>;
 }
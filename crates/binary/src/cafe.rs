@@ -1,48 +1,65 @@
 use std::path::Path;
 use std::path::PathBuf;
-use std::process::Command;
 
-use anyhow::Result;
+use async_trait::async_trait;
+use system::error::Result;
 use system::platform::Platform;
-use system::resources::Resource;
+use system::tool::{self, Observer};
+use tokio::process::Command;
 
-use crate::{compile::Compile, metadata::Metadata};
+use crate::{compile::Compile, metadata::Metadata, romfs::RomFS};
 
 pub struct Cafe;
 
 impl Cafe {
-    fn create_rpx(&self, path: &Path, title: &String) -> Result<PathBuf> {
-        let rpl_path = path.join(format!("{}.rpx", title));
+    async fn create_rpx(
+        &self,
+        path: &Path,
+        metadata: &Metadata,
+        observer: &dyn Observer,
+    ) -> Result<PathBuf> {
+        let rpl_path = path.join(format!("{}.rpx", &metadata.title));
         let program = system::programs::get_binary("elf2rpl");
-        let elf_path = system::resources::fetch(&Platform::Cafe, Resource::ElfBinary);
+        let elf_path = system::resources::fetch_binary(
+            &Platform::Cafe,
+            metadata.lovepotion_version.as_deref(),
+        );
 
-        Command::new(program)
-            .arg(elf_path)
-            .arg(&rpl_path)
-            .output()?;
+        let mut command = Command::new(program);
+        command.arg(elf_path).arg(&rpl_path);
+        tool::run(&mut command, observer).await?;
 
         Ok(rpl_path)
     }
 }
 
+#[async_trait]
 impl Compile for Cafe {
-    fn compile(&self, path: &Path, metadata: &Metadata, icon: &Path) -> Result<PathBuf> {
-        let rpx_path = self.create_rpx(path, &metadata.title)?;
-        let content_path = system::resources::fetch(&Platform::Cafe, Resource::RomFS);
+    async fn compile(
+        &self,
+        path: &Path,
+        metadata: &Metadata,
+        icon: &Path,
+        game: Option<&Path>,
+        observer: &dyn Observer,
+    ) -> Result<PathBuf> {
+        let rpx_path = self.create_rpx(path, metadata, observer).await?;
+        let content = RomFS::stage(&Platform::Cafe, path, game).await?;
         let program = system::programs::get_binary("wuhbtool");
         let output_path = path.join(format!("{}.wuhb", &metadata.title));
 
-        Command::new(program)
+        let mut command = Command::new(program);
+        command
             .arg(&rpx_path)
             .arg(&output_path)
-            .arg(format!("--content={}", content_path.display()))
+            .arg(format!("--content={}", content.path().display()))
             .arg(format!("--name={}", metadata.title))
             .arg(format!("--short-name={}", metadata.title))
             .arg(format!("--author={}", metadata.author))
-            .arg(format!("--icon={icon:?}"))
-            .output()?;
+            .arg(format!("--icon={}", icon.display()));
+        tool::run(&mut command, observer).await?;
 
-        std::fs::remove_file(rpx_path)?;
+        tokio::fs::remove_file(rpx_path).await?;
         Ok(output_path)
     }
 }
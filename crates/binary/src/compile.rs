@@ -2,8 +2,18 @@ use std::path::{Path, PathBuf};
 
 use crate::metadata::Metadata;
 
-use anyhow::Result;
+use async_trait::async_trait;
+use system::error::Result;
+use system::tool::Observer;
 
-pub trait Compile {
-    fn compile(&self, path: &Path, metadata: &Metadata, icon: &Path) -> Result<PathBuf>;
+#[async_trait]
+pub trait Compile: Send + Sync {
+    async fn compile(
+        &self,
+        path: &Path,
+        metadata: &Metadata,
+        icon: &Path,
+        game: Option<&Path>,
+        observer: &dyn Observer,
+    ) -> Result<PathBuf>;
 }
@@ -1,47 +1,65 @@
 use std::path::Path;
 use std::path::PathBuf;
-use std::process::Command;
 
-use anyhow::Result;
+use async_trait::async_trait;
+use system::error::Result;
 use system::platform::Platform;
-use system::resources::Resource;
+use system::tool::{self, Observer};
+use tokio::process::Command;
 
-use crate::{compile::Compile, metadata::Metadata};
+use crate::{compile::Compile, metadata::Metadata, romfs::RomFS};
 
 pub struct Ctr;
 
 impl Ctr {
-    fn create_smdh(&self, path: &Path, metadata: &Metadata, icon: &Path) -> Result<PathBuf> {
+    async fn create_smdh(
+        &self,
+        path: &Path,
+        metadata: &Metadata,
+        icon: &Path,
+        observer: &dyn Observer,
+    ) -> Result<PathBuf> {
         let smdh_path = path.join(format!("{}.smdh", &metadata.title));
         let program = system::programs::get_binary("smdhtool");
-        Command::new(program)
+        let mut command = Command::new(program);
+        command
             .arg("--create")
             .arg(&metadata.title)
             .arg(&metadata.description)
             .arg(&metadata.author)
             .arg(icon)
-            .arg(&smdh_path)
-            .output()?;
+            .arg(&smdh_path);
+        tool::run(&mut command, observer).await?;
         Ok(smdh_path)
     }
 }
 
+#[async_trait]
 impl Compile for Ctr {
-    fn compile(&self, path: &Path, metadata: &Metadata, icon: &Path) -> Result<PathBuf> {
-        let smdh_path = self.create_smdh(path, metadata, icon)?;
-        let elf_path = system::resources::fetch(&Platform::Ctr, Resource::ElfBinary);
-        let romfs_path = system::resources::fetch(&Platform::Ctr, Resource::RomFS);
+    async fn compile(
+        &self,
+        path: &Path,
+        metadata: &Metadata,
+        icon: &Path,
+        game: Option<&Path>,
+        observer: &dyn Observer,
+    ) -> Result<PathBuf> {
+        let smdh_path = self.create_smdh(path, metadata, icon, observer).await?;
+        let elf_path =
+            system::resources::fetch_binary(&Platform::Ctr, metadata.lovepotion_version.as_deref());
+        let romfs = RomFS::stage(&Platform::Ctr, path, game).await?;
         let program = system::programs::get_binary("3dsxtool");
         let output_path = path.join(format!("{}.3dsx", &metadata.title));
 
-        Command::new(program)
+        let mut command = Command::new(program);
+        command
             .arg(elf_path)
             .arg(&output_path)
             .arg(format!("--smdh={}", smdh_path.display()))
-            .arg(format!("--romfs={}", romfs_path.display()))
-            .output()?;
+            .arg(format!("--romfs={}", romfs.path().display()));
+        tool::run(&mut command, observer).await?;
 
-        std::fs::remove_file(smdh_path)?;
+        tokio::fs::remove_file(smdh_path).await?;
         Ok(output_path)
     }
 }
@@ -1,49 +1,69 @@
 use std::path::Path;
 use std::path::PathBuf;
-use std::process::Command;
 
-use anyhow::Result;
+use async_trait::async_trait;
+use system::error::Result;
 use system::platform::Platform;
-use system::resources::Resource;
+use system::tool::{self, Observer};
+use tokio::process::Command;
 
-use crate::{compile::Compile, metadata::Metadata};
+use crate::{compile::Compile, metadata::Metadata, romfs::RomFS};
 
 pub struct Hac;
 
 impl Hac {
-    fn create_nacp(&self, path: &Path, metadata: &Metadata) -> Result<PathBuf> {
+    async fn create_nacp(
+        &self,
+        path: &Path,
+        metadata: &Metadata,
+        observer: &dyn Observer,
+    ) -> Result<PathBuf> {
         let nacp_path = path.join(format!("{}.nacp", &metadata.title));
         let program = system::programs::get_binary("nacptool");
 
-        Command::new(program)
+        let mut command = Command::new(program);
+        command
             .arg("--create")
             .arg(&metadata.title)
             .arg(&metadata.author)
             .arg(&metadata.version)
-            .arg(&nacp_path)
-            .output()?;
+            .arg(&nacp_path);
+        tool::run(&mut command, observer).await?;
 
         Ok(nacp_path)
     }
 }
 
+#[async_trait]
 impl Compile for Hac {
-    fn compile(&self, path: &Path, metadata: &Metadata, icon: &Path) -> Result<PathBuf> {
-        let nacp_path = self.create_nacp(path, metadata)?;
-        let elf_path = system::resources::fetch(&Platform::Hac, Resource::ElfBinary);
-        let romfs_path = system::resources::fetch(&Platform::Hac, Resource::RomFS);
+    async fn compile(
+        &self,
+        path: &Path,
+        metadata: &Metadata,
+        icon: &Path,
+        game: Option<&Path>,
+        observer: &dyn Observer,
+    ) -> Result<PathBuf> {
+        let nacp_path = self.create_nacp(path, metadata, observer).await?;
+        let elf_path =
+            system::resources::fetch_binary(&Platform::Hac, metadata.lovepotion_version.as_deref());
+        let romfs = RomFS::stage(&Platform::Hac, path, game).await?;
         let program = system::programs::get_binary("elf2nro");
         let output_path = path.join(format!("{}.nro", &metadata.title));
 
-        Command::new(program)
+        let mut command = Command::new(program);
+        command
             .arg(elf_path)
             .arg(&output_path)
-            .arg(format!("--icon={icon:?}"))
-            .arg(format!("--nacp={nacp_path:?}"))
-            .arg(format!("--romfs={romfs_path:?}"))
-            .output()?;
+            .arg(format!("--icon={}", icon.display()))
+            .arg(format!("--nacp={}", nacp_path.display()))
+            .arg(match romfs.path().is_dir() {
+                true => format!("--romfsdir={}", romfs.path().display()),
+                false => format!("--romfs={}", romfs.path().display()),
+            });
+        tool::run(&mut command, observer).await?;
 
-        std::fs::remove_file(&nacp_path)?;
+        tokio::fs::remove_file(&nacp_path).await?;
         Ok(output_path)
     }
 }
@@ -3,3 +3,4 @@ pub mod compile;
 pub mod ctr;
 pub mod hac;
 pub mod metadata;
+pub mod romfs;
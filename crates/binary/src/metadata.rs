@@ -1,4 +1,8 @@
+use std::{collections::HashSet, str::FromStr};
+
 use serde::Deserialize;
+use system::error::{Error, Result};
+use system::platform::Platform;
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Metadata {
@@ -7,4 +11,27 @@ pub struct Metadata {
     pub version: String,
     pub description: String,
     pub targets: Vec<String>,
+    /// Release tag of LÖVE Potion to build with, the latest when absent.
+    #[serde(default)]
+    pub lovepotion_version: Option<String>,
+}
+
+impl Metadata {
+    pub fn from_json(json: &str) -> Result<Self> {
+        let mut metadata: Metadata =
+            serde_json::from_str(json).map_err(|e| Error::InvalidConfig(e.to_string()))?;
+        // Targets are kept by their canonical names, which label metrics
+        // and name the directories they are built in.
+        let mut seen = HashSet::new();
+        let mut targets = Vec::with_capacity(metadata.targets.len());
+        for target in metadata.targets {
+            let platform = Platform::from_str(&target)?;
+            let name = platform.to_string();
+            if seen.insert(platform) {
+                targets.push(name);
+            }
+        }
+        metadata.targets = targets;
+        Ok(metadata)
+    }
 }
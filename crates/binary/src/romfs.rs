@@ -0,0 +1,69 @@
+use std::path::{Path, PathBuf};
+
+use system::error::{Error, Result};
+use system::platform::Platform;
+use system::resources::Resource;
+
+const ROMFS_DIRECTORY: &str = "romfs";
+const GAME_DIRECTORY: &str = "game";
+
+pub fn copy_dir(source: &Path, destination: &Path) -> Result<()> {
+    std::fs::create_dir_all(destination)?;
+    for entry in std::fs::read_dir(source)? {
+        let entry = entry?;
+        let target = destination.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            std::fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+pub struct RomFS {
+    path: PathBuf,
+    staged: bool,
+}
+
+impl RomFS {
+    pub async fn stage(platform: &Platform, path: &Path, game: Option<&Path>) -> Result<Self> {
+        let base_path = system::resources::fetch(platform, Resource::RomFS);
+        let Some(game) = game else {
+            return Ok(Self {
+                path: base_path,
+                staged: false,
+            });
+        };
+
+        if !base_path.is_dir() {
+            return Err(Error::MissingResource(base_path));
+        }
+
+        let romfs_path = path.join(ROMFS_DIRECTORY);
+        let (destination, game) = (romfs_path.clone(), game.to_path_buf());
+        tokio::task::spawn_blocking(move || {
+            copy_dir(&base_path, &destination)?;
+            copy_dir(&game, &destination.join(GAME_DIRECTORY))
+        })
+        .await
+        .map_err(std::io::Error::other)??;
+
+        Ok(Self {
+            path: romfs_path,
+            staged: true,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for RomFS {
+    fn drop(&mut self) {
+        if self.staged {
+            let _ = std::fs::remove_dir_all(&self.path);
+        }
+    }
+}
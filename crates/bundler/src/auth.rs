@@ -0,0 +1,464 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use rocket::{
+    Build, Orbit, Request, Rocket,
+    data::ByteUnit,
+    fairing::{self, Fairing, Info, Kind},
+    http::Status,
+    request::{FromRequest, Outcome},
+    tokio,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use log::{error, info};
+
+use crate::config::{AuthConfig, Config};
+use crate::cors::CorsPolicy;
+use crate::error::{ApiError, reject};
+
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// An entry of the keys file, which stores the SHA-256 of each key rather than the key itself.
+#[derive(Deserialize)]
+struct KeyEntry {
+    name: String,
+    sha256: String,
+    /// Requests allowed per window.
+    #[serde(default)]
+    requests: Option<u64>,
+    /// Bytes uploaded and downloaded allowed per window.
+    #[serde(default)]
+    bytes: Option<ByteUnit>,
+    /// Whether the key may use the admin endpoints.
+    #[serde(default)]
+    admin: bool,
+}
+
+#[derive(Deserialize)]
+struct KeyFile {
+    keys: Vec<KeyEntry>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+pub struct Usage {
+    /// Unix time the current window started at.
+    pub since: u64,
+    pub requests: u64,
+    pub bytes: u64,
+}
+
+#[derive(Serialize)]
+pub struct KeyUsage {
+    pub name: String,
+    #[serde(flatten)]
+    pub usage: Usage,
+    pub resets_at: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_limit: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub byte_limit: Option<u64>,
+}
+
+#[derive(Default)]
+struct Store {
+    keys: HashMap<String, KeyEntry>,
+    usage: HashMap<String, Usage>,
+    dirty: bool,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+fn hash(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+/// API keys and their usage counters for the current window, empty unless `auth.enabled` is set.
+#[derive(Clone, Default)]
+pub struct ApiKeys {
+    inner: Arc<Mutex<Store>>,
+    window: u64,
+    enabled: bool,
+}
+
+impl ApiKeys {
+    pub fn load(config: &AuthConfig) -> Result<Self> {
+        let data = std::fs::read(&config.keys_file)
+            .with_context(|| format!("could not read {}", config.keys_file.display()))?;
+        let file: KeyFile = serde_json::from_slice(&data)
+            .with_context(|| format!("could not parse {}", config.keys_file.display()))?;
+        let keys = file
+            .keys
+            .into_iter()
+            .map(|entry| (entry.sha256.to_ascii_lowercase(), entry))
+            .collect();
+
+        let usage = match std::fs::read(&config.usage_file) {
+            Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+                error!("Ignoring unreadable key usage file: {e}");
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+
+        Ok(Self {
+            inner: Arc::new(Mutex::new(Store {
+                keys,
+                usage,
+                dirty: false,
+            })),
+            window: config.window.max(1),
+            enabled: true,
+        })
+    }
+
+    fn store(&self) -> std::sync::MutexGuard<'_, Store> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn current(&self, usage: &mut Usage) {
+        let now = now();
+        if now.saturating_sub(usage.since) >= self.window {
+            *usage = Usage {
+                since: now,
+                ..Usage::default()
+            };
+        }
+    }
+
+    /// Counts a request against the key, returning its name. A request
+    /// declaring a body of `bytes` that would exceed the byte quota is
+    /// refused up front; the bytes are counted once they are received.
+    fn authorize(&self, key: &str, bytes: u64) -> Result<String, ApiError> {
+        let mut store = self.store();
+        let Some(entry) = store.keys.get(&hash(key)) else {
+            return Err(ApiError::new(
+                Status::Unauthorized,
+                "invalid_api_key",
+                "the API key is not known",
+            ));
+        };
+        let (name, requests, limit) = (entry.name.clone(), entry.requests, entry.bytes);
+
+        let mut usage = store.usage.get(&name).copied().unwrap_or_default();
+        self.current(&mut usage);
+        if requests.is_some_and(|limit| usage.requests >= limit) {
+            return Err(ApiError::new(
+                Status::TooManyRequests,
+                "quota_exceeded",
+                format!("request quota for {name} is used up"),
+            ));
+        }
+        if limit.is_some_and(|limit| usage.bytes.saturating_add(bytes) > limit.as_u64()) {
+            return Err(ApiError::new(
+                Status::TooManyRequests,
+                "quota_exceeded",
+                format!("byte quota for {name} is used up"),
+            ));
+        }
+
+        usage.requests += 1;
+        store.usage.insert(name.clone(), usage);
+        store.dirty = true;
+        Ok(name)
+    }
+
+    fn is_admin(&self, key: &str) -> bool {
+        self.store()
+            .keys
+            .get(&hash(key))
+            .is_some_and(|entry| entry.admin)
+    }
+
+    /// Counts bytes exchanged with the key's caller, such as an upload or a
+    /// downloaded artifact.
+    pub fn record_bytes(&self, name: &str, bytes: u64) {
+        let mut store = self.store();
+        let mut usage = store.usage.get(name).copied().unwrap_or_default();
+        self.current(&mut usage);
+        usage.bytes = usage.bytes.saturating_add(bytes);
+        store.usage.insert(name.to_string(), usage);
+        store.dirty = true;
+    }
+
+    /// Looks up the usage of `key` without counting the lookup against it.
+    pub fn usage(&self, key: &ApiKey<'_>) -> Option<KeyUsage> {
+        let mut store = self.store();
+        let entry = store.keys.get(&hash(key.0))?;
+        let name = entry.name.clone();
+        let (request_limit, byte_limit) = (entry.requests, entry.bytes.map(|b| b.as_u64()));
+        let usage = store.usage.entry(name.clone()).or_default();
+        self.current(usage);
+        Some(KeyUsage {
+            name,
+            usage: *usage,
+            resets_at: usage.since + self.window,
+            request_limit,
+            byte_limit,
+        })
+    }
+
+    /// Writes the usage counters to `path` if they changed since the last save.
+    pub async fn save(&self, path: &Path) -> Result<()> {
+        let data = {
+            let mut store = self.store();
+            if !store.dirty {
+                return Ok(());
+            }
+            store.dirty = false;
+            serde_json::to_vec(&store.usage)?
+        };
+        let partial = path.with_extension("tmp");
+        tokio::fs::write(&partial, data).await?;
+        tokio::fs::rename(&partial, path).await?;
+        Ok(())
+    }
+}
+
+/// A request made with an admin key. Admin keys are not metered.
+pub struct Admin;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = ApiError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(keys) = request
+            .rocket()
+            .state::<ApiKeys>()
+            .filter(|keys| keys.enabled)
+        else {
+            // Without `auth.enabled` no keys are loaded, so no caller could be an admin.
+            let error = ApiError::not_found(
+                "admin_disabled",
+                "admin endpoints need auth.enabled and an admin key",
+            );
+            return Outcome::Error((error.status(), reject(request, error)));
+        };
+        if presented_key(request).is_some_and(|key| keys.is_admin(key)) {
+            return Outcome::Success(Self);
+        }
+        let error = ApiError::new(
+            Status::Forbidden,
+            "admin_required",
+            "an admin API key is required",
+        );
+        Outcome::Error((error.status(), reject(request, error)))
+    }
+}
+
+/// Who a request to a build endpoint is made by.
+#[derive(Clone)]
+pub enum Caller {
+    Key(String),
+    Anonymous,
+}
+
+impl Caller {
+    pub fn key(&self) -> Option<&str> {
+        match self {
+            Self::Key(name) => Some(name),
+            Self::Anonymous => None,
+        }
+    }
+}
+
+/// Bytes of uploads a request's route received.
+#[derive(Default)]
+struct Received(AtomicU64);
+
+/// Bytes of uploads received for `request`, which unlike its `Content-Length`
+/// also covers chunked uploads.
+pub fn received(request: &Request<'_>) -> u64 {
+    request
+        .local_cache(Received::default)
+        .0
+        .load(Ordering::Relaxed)
+}
+
+/// Meters what a build route received against the caller and the request.
+pub struct Uploads<'r> {
+    caller: Caller,
+    keys: &'r ApiKeys,
+    received: &'r Received,
+}
+
+impl Uploads<'_> {
+    pub fn record(&self, bytes: u64) {
+        self.received.0.fetch_add(bytes, Ordering::Relaxed);
+        if let Some(name) = self.caller.key() {
+            self.keys.record_bytes(name, bytes);
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Uploads<'r> {
+    type Error = ApiError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let caller = rocket::outcome::try_outcome!(request.guard::<Caller>().await);
+        let Some(keys) = request.rocket().state::<ApiKeys>() else {
+            let error = ApiError::internal("API keys are not managed");
+            return Outcome::Error((error.status(), reject(request, error)));
+        };
+        Outcome::Success(Self {
+            caller,
+            keys,
+            received: request.local_cache(Received::default),
+        })
+    }
+}
+
+fn presented_key<'r>(request: &'r Request<'_>) -> Option<&'r str> {
+    let headers = request.headers();
+    headers.get_one("X-API-Key").or_else(|| {
+        headers
+            .get_one("Authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim)
+    })
+}
+
+/// The API key a request presents, which is not checked or metered.
+pub struct ApiKey<'r>(&'r str);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ApiKey<'r> {
+    type Error = ApiError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match presented_key(request) {
+            Some(key) => Outcome::Success(Self(key)),
+            None => {
+                let error = ApiError::new(
+                    Status::Unauthorized,
+                    "missing_api_key",
+                    "no API key was given",
+                );
+                Outcome::Error((error.status(), reject(request, error)))
+            }
+        }
+    }
+}
+
+async fn identify(request: &Request<'_>) -> Result<Caller, ApiError> {
+    let Some(keys) = request
+        .rocket()
+        .state::<ApiKeys>()
+        .filter(|keys| keys.enabled)
+    else {
+        return Ok(Caller::Anonymous);
+    };
+    if let Some(key) = presented_key(request) {
+        let bytes = request
+            .headers()
+            .get_one("Content-Length")
+            .and_then(|length| length.parse().ok())
+            .unwrap_or(0);
+        return keys.authorize(key, bytes).map(Caller::Key);
+    }
+
+    let allowed = request.headers().get_one("Origin").is_some_and(|origin| {
+        request
+            .rocket()
+            .state::<CorsPolicy>()
+            .is_some_and(|policy| policy.read().allows_origin(origin))
+    });
+    if allowed {
+        return Ok(Caller::Anonymous);
+    }
+    Err(ApiError::new(
+        Status::Unauthorized,
+        "missing_api_key",
+        "an API key is required outside of allowed origins",
+    ))
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Caller {
+    type Error = ApiError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let caller = request
+            .local_cache_async(async { identify(request).await })
+            .await
+            .clone();
+        match caller {
+            Ok(caller) => Outcome::Success(caller),
+            Err(error) => Outcome::Error((error.status(), reject(request, error))),
+        }
+    }
+}
+
+/// Loads the API keys when they are enabled and keeps their usage counters on disk.
+pub struct Auth;
+
+#[rocket::async_trait]
+impl Fairing for Auth {
+    fn info(&self) -> Info {
+        Info {
+            name: "API Keys",
+            kind: Kind::Ignite | Kind::Liftoff | Kind::Shutdown,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        let config = rocket
+            .state::<Config>()
+            .map(|config| config.auth.clone())
+            .unwrap_or_default();
+        if !config.enabled {
+            return Ok(rocket.manage(ApiKeys::default()));
+        }
+        match ApiKeys::load(&config) {
+            Ok(keys) => Ok(rocket.manage(keys)),
+            Err(e) => {
+                error!("Could not load API keys: {e:#}");
+                Err(rocket)
+            }
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        let (Some(config), Some(keys)) = (
+            rocket.state::<Config>(),
+            rocket.state::<ApiKeys>().filter(|keys| keys.enabled),
+        ) else {
+            return;
+        };
+        info!("API keys are required outside of allowed origins");
+        let path = config.auth.usage_file.clone();
+        let keys = keys.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SAVE_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(e) = keys.save(&path).await {
+                    error!("Could not save key usage: {e}");
+                }
+            }
+        });
+    }
+
+    async fn on_shutdown(&self, rocket: &Rocket<Orbit>) {
+        let (Some(config), Some(keys)) = (
+            rocket.state::<Config>(),
+            rocket.state::<ApiKeys>().filter(|keys| keys.enabled),
+        ) else {
+            return;
+        };
+        if let Err(e) = keys.save(&config.auth.usage_file).await {
+            error!("Could not save key usage: {e}");
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use rocket::{
+    Request,
+    request::{FromRequest, Outcome},
+};
+
+use crate::auth::Caller;
+use crate::error::ApiError;
+
+/// An address or `address/prefix` network.
+#[derive(Clone, Copy)]
+pub struct Network {
+    address: IpAddr,
+    prefix: u8,
+}
+
+impl Network {
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self.address, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Network {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (address, prefix) = value.split_once('/').unwrap_or((value, ""));
+        let address = IpAddr::from_str(address)
+            .map_err(|_| format!("{value:?} is not an IP address"))?
+            .to_canonical();
+        let bits = if address.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            "" => bits,
+            prefix => prefix
+                .parse()
+                .ok()
+                .filter(|prefix| *prefix <= bits)
+                .ok_or_else(|| format!("{value:?} has an invalid prefix length"))?,
+        };
+        Ok(Self { address, prefix })
+    }
+}
+
+/// Proxies whose `X-Forwarded-For` header is believed.
+#[derive(Default)]
+pub struct TrustedProxies(Vec<Network>);
+
+impl TrustedProxies {
+    pub fn new(networks: Vec<Network>) -> Self {
+        Self(networks)
+    }
+
+    fn trusts(&self, ip: IpAddr) -> bool {
+        self.0.iter().any(|network| network.contains(ip))
+    }
+
+    /// The address of the client, walking `X-Forwarded-For` back through trusted proxies.
+    pub fn client_ip(&self, request: &Request<'_>) -> Option<IpAddr> {
+        let mut ip = request.remote()?.ip().to_canonical();
+        let forwarded = request
+            .headers()
+            .get("X-Forwarded-For")
+            .flat_map(|value| value.split(','))
+            .collect::<Vec<_>>();
+        for hop in forwarded.iter().rev() {
+            if !self.trusts(ip) {
+                break;
+            }
+            match IpAddr::from_str(hop.trim()) {
+                Ok(hop) => ip = hop.to_canonical(),
+                Err(_) => break,
+            }
+        }
+        Some(ip)
+    }
+}
+
+/// Identifies the caller of a request for fair scheduling, by API key when one was given.
+pub struct Client(String);
+
+impl Client {
+    pub fn id(&self) -> &str {
+        &self.0
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Client {
+    type Error = ApiError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let caller = rocket::outcome::try_outcome!(request.guard::<Caller>().await);
+        let ip = match request.rocket().state::<TrustedProxies>() {
+            Some(proxies) => proxies.client_ip(request),
+            None => request.client_ip(),
+        };
+        let id = match (caller.key(), ip) {
+            (Some(name), _) => format!("key:{name}"),
+            (None, Some(ip)) => ip.to_string(),
+            (None, None) => String::from("unknown"),
+        };
+        Outcome::Success(Self(id))
+    }
+}
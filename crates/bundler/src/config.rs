@@ -0,0 +1,360 @@
+use std::collections::BTreeMap;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::thread::available_parallelism;
+use std::time::Duration;
+
+use anyhow::{Result, bail};
+use asset::archive::Limits;
+use rocket::{data::ByteUnit, http::Method};
+use serde::Deserialize;
+use system::tool::Timeouts;
+
+use crate::client::Network;
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct ArtifactsConfig {
+    /// Directory holding one subdirectory and build log per token.
+    pub directory: PathBuf,
+    /// Seconds after which an untouched token directory is removed.
+    pub ttl: u64,
+    /// Maximum total size of the artifacts directory.
+    pub quota: ByteUnit,
+    /// Seconds between sweeps of the artifacts directory.
+    pub sweep_interval: u64,
+}
+
+impl ArtifactsConfig {
+    pub fn ttl(&self) -> Duration {
+        Duration::from_secs(self.ttl)
+    }
+
+    pub fn sweep_interval(&self) -> Duration {
+        Duration::from_secs(self.sweep_interval.max(1))
+    }
+}
+
+impl Default for ArtifactsConfig {
+    fn default() -> Self {
+        Self {
+            directory: PathBuf::from(".artifacts"),
+            ttl: 60 * 60,
+            quota: ByteUnit::Gibibyte(2),
+            sweep_interval: 5 * 60,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct ArchiveConfig {
+    /// Largest total size an uploaded game archive may expand to.
+    pub max_size: ByteUnit,
+    /// Most entries an uploaded game archive may hold.
+    pub max_files: usize,
+}
+
+impl ArchiveConfig {
+    pub fn limits(&self) -> Limits {
+        Limits {
+            bytes: self.max_size.as_u64(),
+            files: self.max_files,
+        }
+    }
+}
+
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        Self {
+            max_size: ByteUnit::Mebibyte(256),
+            max_files: 10_000,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct RetentionConfig {
+    /// Completed downloads allowed per file before it is removed, `0` for no limit.
+    pub downloads: u32,
+    /// Seconds a token stays downloadable after it was created, `0` for no limit.
+    pub window: u64,
+}
+
+impl RetentionConfig {
+    pub fn window(&self) -> Duration {
+        Duration::from_secs(self.window)
+    }
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            downloads: 3,
+            window: 30 * 60,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct ToolsConfig {
+    /// Seconds a tool may run before it is killed, `0` for no limit.
+    pub timeout: u64,
+    /// Per-tool overrides of `timeout`, keyed by program name.
+    pub timeouts: BTreeMap<String, u64>,
+}
+
+impl ToolsConfig {
+    pub fn timeouts(&self) -> Timeouts {
+        let limit = |seconds: u64| (seconds > 0).then(|| Duration::from_secs(seconds));
+        Timeouts {
+            default: limit(self.timeout),
+            tools: self
+                .timeouts
+                .iter()
+                .map(|(program, seconds)| (program.clone(), limit(*seconds)))
+                .collect(),
+        }
+    }
+}
+
+impl Default for ToolsConfig {
+    fn default() -> Self {
+        Self {
+            timeout: 5 * 60,
+            timeouts: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct CorsRoute {
+    /// Path prefix the route applies to.
+    pub path: String,
+    pub methods: Vec<String>,
+    /// Request headers allowed on the route, echoing the requested ones when empty.
+    #[serde(default)]
+    pub headers: Vec<String>,
+}
+
+impl CorsRoute {
+    fn new(path: &str, methods: &[&str]) -> Self {
+        Self {
+            path: path.to_string(),
+            methods: methods.iter().map(|method| method.to_string()).collect(),
+            headers: Vec::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct CorsConfig {
+    /// Allowed origins as `scheme://host[:port]`, where `*` matches part of the host.
+    pub origins: Vec<String>,
+    pub routes: Vec<CorsRoute>,
+    /// Response headers that scripts on allowed origins may read.
+    pub expose_headers: Vec<String>,
+    /// Whether browsers may send credentials with cross-origin requests.
+    pub credentials: bool,
+    /// Seconds a preflight response may be cached.
+    pub max_age: u64,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        let mut origins = vec![
+            String::from("https://lovebrew.github.io"),
+            String::from("https://bundle.lovebrew.org"),
+        ];
+        if cfg!(debug_assertions) {
+            origins.push(String::from("http://localhost:3000"));
+        }
+        let expose_headers = [
+            "Accept-Ranges",
+            "Content-Disposition",
+            "Content-Range",
+            "ETag",
+            "RateLimit-Limit",
+            "RateLimit-Remaining",
+            "RateLimit-Reset",
+            "Retry-After",
+        ];
+        Self {
+            origins,
+            routes: vec![
+                CorsRoute::new("/convert", &["POST"]),
+                CorsRoute::new("/compile", &["POST"]),
+                CorsRoute::new("/bundle", &["POST"]),
+                CorsRoute::new("/artifact", &["GET"]),
+                CorsRoute::new("/jobs", &["GET", "DELETE"]),
+                CorsRoute::new("/events", &["GET"]),
+            ],
+            expose_headers: expose_headers.map(String::from).to_vec(),
+            credentials: false,
+            max_age: 24 * 60 * 60,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct RateLimitRoute {
+    /// Path prefix the budget applies to.
+    pub path: String,
+    /// Requests a client may make at once, refilled evenly over `period`.
+    pub requests: u32,
+    /// Seconds it takes to refill the budget.
+    pub period: u64,
+}
+
+impl RateLimitRoute {
+    fn new(path: &str, requests: u32, period: u64) -> Self {
+        Self {
+            path: path.to_string(),
+            requests,
+            period,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    /// Proxy addresses or networks whose `X-Forwarded-For` header is trusted.
+    pub trusted_proxies: Vec<String>,
+    pub routes: Vec<RateLimitRoute>,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            trusted_proxies: vec![String::from("127.0.0.1"), String::from("::1")],
+            routes: vec![
+                RateLimitRoute::new("/convert", 30, 60),
+                RateLimitRoute::new("/compile", 10, 60),
+                RateLimitRoute::new("/bundle", 10, 60),
+            ],
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct PoolConfig {
+    /// Tools allowed to run at once, `0` for one per available core.
+    pub slots: usize,
+}
+
+impl PoolConfig {
+    pub fn slots(&self) -> usize {
+        match self.slots {
+            0 => available_parallelism().map_or(1, NonZeroUsize::get),
+            slots => slots,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct AuthConfig {
+    /// Whether requests from outside the allowed origins need an API key.
+    pub enabled: bool,
+    /// JSON file listing each key's name, SHA-256 and quotas.
+    pub keys_file: PathBuf,
+    /// File the per-key usage counters are kept in.
+    pub usage_file: PathBuf,
+    /// Seconds after which the usage counters start over.
+    pub window: u64,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            keys_file: PathBuf::from("keys.json"),
+            usage_file: PathBuf::from(".usage.json"),
+            window: 24 * 60 * 60,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Config {
+    pub artifacts: ArtifactsConfig,
+    pub archive: ArchiveConfig,
+    pub retention: RetentionConfig,
+    pub tools: ToolsConfig,
+    pub pool: PoolConfig,
+    pub cors: CorsConfig,
+    pub auth: AuthConfig,
+    pub rate_limit: RateLimitConfig,
+    pub system: system::config::Config,
+}
+
+impl Config {
+    pub fn validate(&self) -> Result<()> {
+        if self.artifacts.directory.as_os_str().is_empty() {
+            bail!("artifacts.directory must not be empty");
+        }
+        if self.archive.max_size.as_u64() == 0 || self.archive.max_files == 0 {
+            bail!("archive.max_size and archive.max_files must allow a game");
+        }
+        for origin in &self.cors.origins {
+            if origin == "*" {
+                if self.cors.credentials {
+                    bail!("cors.origins cannot contain \"*\" while cors.credentials is set");
+                }
+                continue;
+            }
+            let host = origin
+                .strip_prefix("https://")
+                .or_else(|| origin.strip_prefix("http://"));
+            if !host.is_some_and(|host| !host.is_empty() && !host.contains('/')) {
+                bail!("cors.origins entry {origin:?} is not an http(s) origin");
+            }
+        }
+        for route in &self.cors.routes {
+            if !route.path.starts_with('/') {
+                bail!("cors.routes path {:?} must start with '/'", route.path);
+            }
+            if let Some(method) = route.methods.iter().find(|m| Method::from_str(m).is_err()) {
+                bail!(
+                    "cors.routes method {method:?} for {:?} is unknown",
+                    route.path
+                );
+            }
+        }
+        for proxy in &self.rate_limit.trusted_proxies {
+            if let Err(e) = Network::from_str(proxy) {
+                bail!("rate_limit.trusted_proxies entry {e}");
+            }
+        }
+        for route in &self.rate_limit.routes {
+            if !route.path.starts_with('/') {
+                bail!(
+                    "rate_limit.routes path {:?} must start with '/'",
+                    route.path
+                );
+            }
+            if route.requests == 0 || route.period == 0 {
+                bail!(
+                    "rate_limit.routes budget for {:?} must allow requests",
+                    route.path
+                );
+            }
+        }
+        if self.auth.enabled && !self.auth.keys_file.is_file() {
+            bail!(
+                "auth.keys_file {} does not exist",
+                self.auth.keys_file.display()
+            );
+        }
+        self.system.validate()
+    }
+}
@@ -1,35 +1,112 @@
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard};
+
+use anyhow::Result;
 use rocket::{
-    Request, Response,
-    fairing::{Fairing, Info, Kind},
+    Build, Orbit, Request, Response, Rocket,
+    fairing::{self, Fairing, Info, Kind},
     http::{Header, Method, Status},
 };
-use std::{collections::HashSet, sync::LazyLock};
 
-use log::error;
+use log::{error, info, warn};
 
-pub struct Cors;
+use crate::config::{Config, CorsConfig, CorsRoute};
+use crate::ratelimit::requested_path;
+
+fn is_host_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '.'
+}
 
-static ALLOWED_ORIGINS: LazyLock<HashSet<&'static str>> = LazyLock::new(|| {
-    let mut set: HashSet<&'static str> =
-        ["https://lovebrew.github.io", "https://bundle.lovebrew.org"]
-            .into_iter()
-            .collect();
+/// Matches `rest` against the pattern pieces that follow each `*`, where a
+/// `*` stands for one or more host characters.
+fn matches_wildcards(parts: &[&str], rest: &str) -> bool {
+    let Some((part, remaining)) = parts.split_first() else {
+        return rest.is_empty();
+    };
+    let run = rest.find(|c| !is_host_char(c)).unwrap_or(rest.len());
+    (1..=run).any(|n| {
+        rest[n..]
+            .strip_prefix(part)
+            .is_some_and(|rest| matches_wildcards(remaining, rest))
+    })
+}
+
+pub fn origin_matches(pattern: &str, origin: &str) -> bool {
+    if pattern == "*" {
+        return true;
+    }
+    let parts: Vec<_> = pattern.split('*').collect();
+    origin
+        .strip_prefix(parts[0])
+        .is_some_and(|rest| matches_wildcards(&parts[1..], rest))
+}
 
-    #[cfg(debug_assertions)]
-    {
-        set.insert("http://localhost:3000");
+impl CorsConfig {
+    pub fn allows_origin(&self, origin: &str) -> bool {
+        self.origins
+            .iter()
+            .any(|pattern| origin_matches(pattern, origin))
     }
-    set
-});
 
-static CORS_PATHS: LazyLock<HashSet<&'static str>> =
-    LazyLock::new(|| HashSet::from(["/convert", "/compile", "/artifact"]));
+    fn route(&self, path: &str) -> Option<&CorsRoute> {
+        self.routes.iter().find(|route| {
+            path.strip_prefix(&route.path)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+    }
+}
+
+/// The CORS policy in effect, replaced whenever the configuration is reloaded.
+#[derive(Clone)]
+pub struct CorsPolicy {
+    inner: Arc<RwLock<CorsConfig>>,
+}
+
+impl CorsPolicy {
+    pub fn new(config: CorsConfig) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(config)),
+        }
+    }
+
+    pub fn read(&self) -> RwLockReadGuard<'_, CorsConfig> {
+        self.inner.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn reload(&self) -> Result<()> {
+        let config: Config = rocket::Config::figment().extract()?;
+        config.validate()?;
+        *self.inner.write().unwrap_or_else(PoisonError::into_inner) = config.cors;
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn reload_on_hangup(policy: CorsPolicy) {
+    use rocket::tokio::signal::unix::{SignalKind, signal};
+
+    rocket::tokio::spawn(async move {
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(hangup) => hangup,
+            Err(e) => {
+                error!("Could not listen for SIGHUP: {e}");
+                return;
+            }
+        };
+        while hangup.recv().await.is_some() {
+            match policy.reload() {
+                Ok(()) => info!("Reloaded CORS policy"),
+                Err(e) => error!("Could not reload CORS policy: {e}"),
+            }
+        }
+    });
+}
 
 fn set_cors_headers(
     response: &mut Response<'_>,
     origin: impl ToString,
     methods: impl ToString,
     headers: impl ToString,
+    max_age: u64,
 ) {
     response.set_header(Header::new(
         "Access-Control-Allow-Origin",
@@ -43,15 +120,66 @@ fn set_cors_headers(
         "Access-Control-Allow-Headers",
         headers.to_string(),
     ));
-    response.set_header(Header::new("Access-Control-Max-Age", "86400"));
+    response.set_header(Header::new("Access-Control-Max-Age", max_age.to_string()));
 }
 
+fn allow_route(
+    response: &mut Response<'_>,
+    config: &CorsConfig,
+    route: &CorsRoute,
+    origin: &str,
+    requested_headers: &str,
+) {
+    let mut methods = route.methods.clone();
+    if !methods.iter().any(|method| method == "OPTIONS") {
+        methods.push(String::from("OPTIONS"));
+    }
+    let headers = match route.headers.is_empty() {
+        true => requested_headers.to_string(),
+        false => route.headers.join(", "),
+    };
+    set_cors_headers(
+        response,
+        origin,
+        methods.join(", "),
+        headers,
+        config.max_age,
+    );
+    response.set_header(Header::new("Vary", "Origin"));
+    if !config.expose_headers.is_empty() {
+        response.set_header(Header::new(
+            "Access-Control-Expose-Headers",
+            config.expose_headers.join(", "),
+        ));
+    }
+    if config.credentials {
+        response.set_header(Header::new("Access-Control-Allow-Credentials", "true"));
+    }
+}
+
+pub struct Cors;
+
 #[rocket::async_trait]
 impl Fairing for Cors {
     fn info(&self) -> Info {
         Info {
             name: "Selective CORS Headers",
-            kind: Kind::Response,
+            kind: Kind::Ignite | Kind::Liftoff | Kind::Response,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        let config = rocket
+            .state::<Config>()
+            .map(|config| config.cors.clone())
+            .unwrap_or_default();
+        Ok(rocket.manage(CorsPolicy::new(config)))
+    }
+
+    async fn on_liftoff(&self, _rocket: &Rocket<Orbit>) {
+        #[cfg(unix)]
+        if let Some(policy) = _rocket.state::<CorsPolicy>() {
+            reload_on_hangup(policy.clone());
         }
     }
 
@@ -61,33 +189,100 @@ impl Fairing for Cors {
             .get_one("Access-Control-Request-Headers")
             .unwrap_or("Content-Type, Authorization");
 
-        let path = request.uri().path();
+        let path = requested_path(request);
+        let Some(policy) = request.rocket().state::<CorsPolicy>() else {
+            return;
+        };
+        let config = policy.read();
 
-        if path == "/health" {
-            set_cors_headers(response, "*", "GET, OPTIONS", req_headers);
+        if path == "/health" || path.starts_with("/health/") {
+            set_cors_headers(response, "*", "GET, OPTIONS", req_headers, config.max_age);
             return;
         }
 
-        if CORS_PATHS.contains(path.as_str()) {
-            let origin = headers.get_one("Origin");
-            if let Some(origin) = origin {
-                if ALLOWED_ORIGINS.contains(&origin) {
-                    set_cors_headers(response, origin, "POST, OPTIONS", req_headers);
-                } else {
-                    error!("Unauthorized CORS origin: {origin}!");
+        let method = request.method();
+
+        if let (Some(route), Some(origin)) = (config.route(path), headers.get_one("Origin")) {
+            if config.allows_origin(origin) {
+                allow_route(response, &config, route, origin, req_headers);
+            } else {
+                warn!("Unauthorized CORS origin: {origin}!");
+                if method == Method::Options {
+                    response.set_status(Status::Forbidden);
+                    return;
                 }
             }
         }
 
-        let method = request.method();
-
         if method == Method::Options {
             if response.status() == Status::NotFound {
                 response.set_status(Status::NoContent);
             }
             if !response.headers().contains("Access-Control-Allow-Origin") {
-                set_cors_headers(response, "*", "OPTIONS", req_headers);
+                set_cors_headers(response, "*", "OPTIONS", req_headers, config.max_age);
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::origin_matches;
+
+    #[test]
+    fn exact_patterns_match_only_themselves() {
+        assert!(origin_matches(
+            "https://lovebrew.org",
+            "https://lovebrew.org"
+        ));
+        assert!(!origin_matches(
+            "https://lovebrew.org",
+            "https://lovebrew.org.evil.com"
+        ));
+        assert!(!origin_matches(
+            "https://lovebrew.org",
+            "http://lovebrew.org"
+        ));
+    }
+
+    #[test]
+    fn star_alone_matches_any_origin() {
+        assert!(origin_matches("*", "https://example.com"));
+        assert!(origin_matches("*", ""));
+    }
+
+    #[test]
+    fn wildcards_match_host_characters() {
+        let pattern = "https://*.lovebrew.org";
+        assert!(origin_matches(pattern, "https://www.lovebrew.org"));
+        assert!(origin_matches(pattern, "https://a.b.lovebrew.org"));
+        assert!(origin_matches(pattern, "https://pr-12.lovebrew.org"));
+        assert!(!origin_matches(pattern, "https://.lovebrew.org"));
+        assert!(!origin_matches(pattern, "https://lovebrew.org"));
+    }
+
+    #[test]
+    fn wildcards_do_not_cross_the_host() {
+        assert!(!origin_matches(
+            "https://*.lovebrew.org",
+            "https://evil.com/.lovebrew.org"
+        ));
+        assert!(!origin_matches(
+            "https://*.lovebrew.org",
+            "https://evil.com:1.lovebrew.org"
+        ));
+        assert!(!origin_matches(
+            "https://*.lovebrew.org",
+            "https://www.lovebrew.org.evil.com"
+        ));
+    }
+
+    #[test]
+    fn wildcards_match_ports() {
+        let pattern = "http://localhost:*";
+        assert!(origin_matches(pattern, "http://localhost:3000"));
+        assert!(!origin_matches(pattern, "http://localhost:"));
+        assert!(!origin_matches(pattern, "http://localhost"));
+        assert!(origin_matches("http://*:*", "http://127.0.0.1:8080"));
+    }
+}
@@ -0,0 +1,255 @@
+use std::fs::Metadata;
+use std::io::SeekFrom;
+use std::time::UNIX_EPOCH;
+
+use rocket::{
+    Request, Response,
+    http::{ContentType, Header, Status},
+    request::{FromRequest, Outcome},
+    response::{self, Responder},
+    tokio::{
+        fs::File,
+        io::{AsyncReadExt, AsyncSeekExt},
+    },
+};
+
+#[derive(Clone, Copy)]
+pub struct ByteRange {
+    start: u64,
+    end: u64,
+}
+
+impl ByteRange {
+    fn parse(value: &str, total: u64) -> Result<Option<Self>, Status> {
+        let Some(spec) = value.trim().strip_prefix("bytes=") else {
+            return Ok(None);
+        };
+        if spec.contains(',') {
+            return Ok(None);
+        }
+        Self::bounds(spec, total)
+            .map(Some)
+            .ok_or(Status::RangeNotSatisfiable)
+    }
+
+    fn bounds(spec: &str, total: u64) -> Option<Self> {
+        let (start, end) = spec.split_once('-')?;
+        let (start, end) = match (start.trim(), end.trim()) {
+            ("", suffix) => {
+                let suffix: u64 = suffix.parse().ok()?;
+                (total.checked_sub(suffix.min(total))?, total.checked_sub(1)?)
+            }
+            (start, "") => (start.parse().ok()?, total.checked_sub(1)?),
+            (start, end) => (start.parse().ok()?, end.parse::<u64>().ok()?),
+        };
+        let end = end.min(total.checked_sub(1)?);
+        if start > end {
+            return None;
+        }
+        Some(Self { start, end })
+    }
+}
+
+pub struct Conditions<'r> {
+    range: Option<&'r str>,
+    if_none_match: Option<&'r str>,
+}
+
+impl Conditions<'_> {
+    /// Whether the client already holds the version tagged `etag`.
+    pub fn matches(&self, etag: &str) -> bool {
+        self.if_none_match.is_some_and(|value| {
+            value.trim() == "*" || value.split(',').any(|tag| tag.trim() == etag)
+        })
+    }
+}
+
+/// The strong ETag of an artifact with the SHA-256 `digest`.
+pub fn etag(digest: &str) -> String {
+    format!("\"{digest}\"")
+}
+
+/// A weak ETag from an artifact's size and modification time, for artifacts
+/// whose digest is no longer known, e.g. after a restart.
+pub fn weak_etag(metadata: &Metadata) -> String {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |elapsed| elapsed.as_nanos());
+    format!("W/\"{:x}-{modified:x}\"", metadata.len())
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Conditions<'r> {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let headers = request.headers();
+        Outcome::Success(Self {
+            range: headers.get_one("Range"),
+            if_none_match: headers.get_one("If-None-Match"),
+        })
+    }
+}
+
+/// An artifact download, streamed from the file so that large artifacts
+/// and many parallel downloads are never held in memory.
+pub struct Download {
+    status: Status,
+    file: Option<File>,
+    total: u64,
+    etag: String,
+    content_type: ContentType,
+    range: Option<ByteRange>,
+}
+
+impl Download {
+    /// A `304` for a client that already holds the version tagged `etag`.
+    pub fn not_modified(etag: String, content_type: ContentType) -> Self {
+        Self {
+            status: Status::NotModified,
+            file: None,
+            total: 0,
+            etag,
+            content_type,
+            range: None,
+        }
+    }
+
+    /// Serves `file`, or the part of it the request's `Range` asks for.
+    pub async fn new(
+        mut file: File,
+        etag: String,
+        content_type: ContentType,
+        conditions: &Conditions<'_>,
+    ) -> std::io::Result<Self> {
+        let total = file.metadata().await?.len();
+        let mut download = Self {
+            status: Status::Ok,
+            file: None,
+            total,
+            etag,
+            content_type,
+            range: None,
+        };
+
+        if let Some(value) = conditions.range {
+            match ByteRange::parse(value, total) {
+                Ok(Some(range)) => {
+                    file.seek(SeekFrom::Start(range.start)).await?;
+                    download.status = Status::PartialContent;
+                    download.range = Some(range);
+                }
+                Ok(None) => {}
+                Err(status) => download.status = status,
+            }
+        }
+        download.file = Some(file);
+        Ok(download)
+    }
+
+    /// Bytes the response body will hold, used for metering downloads.
+    pub fn body_len(&self) -> u64 {
+        if self.status == Status::Ok {
+            return self.total;
+        }
+        match self.range {
+            Some(range) if self.status == Status::PartialContent => range.end + 1 - range.start,
+            _ => 0,
+        }
+    }
+
+    /// Whether the response serves the whole artifact, so it counts as a download.
+    pub fn is_complete(&self) -> bool {
+        if self.status == Status::Ok {
+            return true;
+        }
+        self.status == Status::PartialContent
+            && self
+                .range
+                .is_some_and(|range| range.start == 0 && range.end + 1 >= self.total)
+    }
+}
+
+impl<'r> Responder<'r, 'static> for Download {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = Response::build();
+        let total = self.total;
+        let length = self.body_len();
+        response
+            .status(self.status)
+            .header(Header::new("Accept-Ranges", "bytes"));
+
+        if self.status == Status::NotModified {
+            response.header(Header::new("ETag", self.etag));
+            return response.ok();
+        }
+        if self.status == Status::RangeNotSatisfiable {
+            response.header(Header::new("Content-Range", format!("bytes */{total}")));
+            return response.ok();
+        }
+
+        if let Some(ByteRange { start, end }) = self.range {
+            response.header(Header::new(
+                "Content-Range",
+                format!("bytes {start}-{end}/{total}"),
+            ));
+        }
+        // The file is already positioned at the start of the range; the
+        // length is given up front since a streamed body has none.
+        if let Some(file) = self.file {
+            response.streamed_body(file.take(length));
+        }
+        response
+            .header(self.content_type)
+            .header(Header::new("ETag", self.etag))
+            .header(Header::new("Content-Length", length.to_string()))
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ByteRange;
+
+    fn bounds(spec: &str, total: u64) -> Option<(u64, u64)> {
+        ByteRange::bounds(spec, total).map(|range| (range.start, range.end))
+    }
+
+    #[test]
+    fn closed_ranges_are_clamped_to_the_file() {
+        assert_eq!(bounds("0-9", 100), Some((0, 9)));
+        assert_eq!(bounds(" 10 - 19 ", 100), Some((10, 19)));
+        assert_eq!(bounds("90-200", 100), Some((90, 99)));
+    }
+
+    #[test]
+    fn open_ranges_run_to_the_end() {
+        assert_eq!(bounds("0-", 100), Some((0, 99)));
+        assert_eq!(bounds("42-", 100), Some((42, 99)));
+    }
+
+    #[test]
+    fn suffix_ranges_count_from_the_end() {
+        assert_eq!(bounds("-1", 100), Some((99, 99)));
+        assert_eq!(bounds("-10", 100), Some((90, 99)));
+        assert_eq!(bounds("-500", 100), Some((0, 99)));
+    }
+
+    #[test]
+    fn unsatisfiable_ranges_are_rejected() {
+        assert_eq!(bounds("100-", 100), None);
+        assert_eq!(bounds("20-10", 100), None);
+        assert_eq!(bounds("0-0", 0), None);
+        assert_eq!(bounds("-1", 0), None);
+    }
+
+    #[test]
+    fn malformed_ranges_are_rejected() {
+        assert_eq!(bounds("", 100), None);
+        assert_eq!(bounds("10", 100), None);
+        assert_eq!(bounds("a-b", 100), None);
+        assert_eq!(bounds("-", 100), None);
+    }
+}
@@ -0,0 +1,139 @@
+use std::fmt::Display;
+use std::io::Cursor;
+
+use rocket::{
+    Request, Response,
+    http::{ContentType, Status},
+    response::{self, Responder},
+};
+use serde::Serialize;
+use system::error::Error;
+use uuid::Uuid;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ApiError {
+    #[serde(skip)]
+    status: Status,
+    code: &'static str,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    field: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file: Option<String>,
+    /// Arguments of the tool invocation that failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    args: Option<Box<[String]>>,
+    /// Token of the job or artifacts the failed request concerned.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<Uuid>,
+}
+
+impl ApiError {
+    pub fn new(status: Status, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            message: message.into(),
+            field: None,
+            file: None,
+            args: None,
+            token: None,
+        }
+    }
+
+    pub fn bad_request(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(Status::BadRequest, code, message)
+    }
+
+    pub fn not_found(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(Status::NotFound, code, message)
+    }
+
+    pub fn internal(error: impl Display) -> Self {
+        Self::new(
+            Status::InternalServerError,
+            "internal_error",
+            error.to_string(),
+        )
+    }
+
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    pub fn with_field(mut self, field: &'static str) -> Self {
+        self.field = Some(field);
+        self
+    }
+
+    pub fn with_file(mut self, file: &str) -> Self {
+        self.file = Some(file.to_string());
+        self
+    }
+
+    pub fn with_token(mut self, token: Uuid) -> Self {
+        self.token = Some(token);
+        self
+    }
+}
+
+impl From<Error> for ApiError {
+    fn from(error: Error) -> Self {
+        let (status, field) = match &error {
+            Error::InvalidConfig(_) => (Status::BadRequest, Some("config")),
+            Error::UnknownPlatform(_) => (Status::BadRequest, Some("targets")),
+            Error::InvalidIcon(_) => (Status::BadRequest, Some("icon")),
+            Error::InvalidArchive(_) => (Status::BadRequest, Some("game")),
+            Error::UnknownVersion { .. } => (Status::BadRequest, Some("lovepotion_version")),
+            Error::InvalidImage(_) | Error::InvalidFont(_) => (Status::BadRequest, None),
+            Error::MissingResource(_)
+            | Error::Tool { .. }
+            | Error::ToolFailed { .. }
+            | Error::TimedOut { .. }
+            | Error::Io(_) => (Status::InternalServerError, None),
+        };
+        let mut api_error = Self::new(status, error.code(), error.to_string());
+        api_error.args = error.args().map(Box::from);
+        match field {
+            Some(field) => api_error.with_field(field),
+            None => api_error,
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        let body = serde_json::to_string(&self).map_err(|_| Status::InternalServerError)?;
+        Response::build()
+            .status(self.status)
+            .header(ContentType::JSON)
+            .sized_body(body.len(), Cursor::new(body))
+            .ok()
+    }
+}
+
+/// The error a request guard failed with, kept for the catcher to respond with.
+struct Rejection(Option<ApiError>);
+
+/// Stores `error` so that the catcher responds with it instead of a generic body.
+pub fn reject(request: &Request<'_>, error: ApiError) -> ApiError {
+    request.local_cache(|| Rejection(Some(error.clone())));
+    error
+}
+
+#[catch(default)]
+pub fn default_catcher(status: Status, request: &Request) -> ApiError {
+    if let Some(error) = &request.local_cache(|| Rejection(None)).0 {
+        return error.clone();
+    }
+    let code = match status.code {
+        400 => "bad_request",
+        404 => "not_found",
+        413 => "payload_too_large",
+        415 => "unsupported_media_type",
+        422 => "invalid_form",
+        _ if status.class().is_server_error() => "internal_error",
+        _ => "request_failed",
+    };
+    ApiError::new(status, code, status.reason_lossy())
+}
@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use std::process::Output;
+use std::sync::{Arc, Mutex};
+
+use rocket::tokio::sync::broadcast::{self, Receiver, Sender};
+use serde::Serialize;
+use system::tool::{Invocation, Observer};
+use uuid::Uuid;
+
+use crate::{logger::Tracefile, metrics, response::ArtifactResponse, routes::trace_path};
+
+const CHANNEL_CAPACITY: usize = 64;
+
+#[derive(Serialize, Clone)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum BuildEvent {
+    Validated { file: String, valid: bool },
+    Queued { task: String, position: usize },
+    ToolStarted { program: String },
+    ToolFinished { program: String, success: bool },
+    Ready { artifacts: ArtifactResponse },
+    Failed,
+    Cancelled,
+}
+
+impl BuildEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BuildEvent::Validated { .. } => "validated",
+            BuildEvent::Queued { .. } => "queued",
+            BuildEvent::ToolStarted { .. } => "tool_started",
+            BuildEvent::ToolFinished { .. } => "tool_finished",
+            BuildEvent::Ready { .. } => "ready",
+            BuildEvent::Failed => "failed",
+            BuildEvent::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn is_final(&self) -> bool {
+        matches!(
+            self,
+            BuildEvent::Ready { .. } | BuildEvent::Failed | BuildEvent::Cancelled
+        )
+    }
+}
+
+struct Channel {
+    history: Vec<BuildEvent>,
+    sender: Sender<BuildEvent>,
+}
+
+pub struct Subscription {
+    pub history: Vec<BuildEvent>,
+    pub receiver: Option<Receiver<BuildEvent>>,
+}
+
+#[derive(Clone, Default)]
+pub struct Events {
+    inner: Arc<Mutex<HashMap<Uuid, Channel>>>,
+}
+
+impl Events {
+    pub fn reporter(&self, token: Uuid) -> Reporter {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        let channel = Channel {
+            history: Vec::new(),
+            sender,
+        };
+        if let Ok(mut channels) = self.inner.lock() {
+            channels.entry(token).or_insert(channel);
+        }
+        Reporter {
+            token,
+            events: self.clone(),
+            trace: Tracefile::default(),
+        }
+    }
+
+    pub fn subscribe(&self, token: &Uuid) -> Option<Subscription> {
+        let channels = self.inner.lock().ok()?;
+        let channel = channels.get(token)?;
+        let finished = channel.history.last().is_some_and(BuildEvent::is_final);
+        Some(Subscription {
+            history: channel.history.clone(),
+            receiver: (!finished).then(|| channel.sender.subscribe()),
+        })
+    }
+
+    pub fn remove(&self, token: &Uuid) {
+        if let Ok(mut channels) = self.inner.lock() {
+            channels.remove(token);
+        }
+    }
+
+    fn emit(&self, token: &Uuid, event: BuildEvent) {
+        let Ok(mut channels) = self.inner.lock() else {
+            return;
+        };
+        if let Some(channel) = channels.get_mut(token) {
+            channel.history.push(event.clone());
+            let _ = channel.sender.send(event);
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Reporter {
+    token: Uuid,
+    events: Events,
+    trace: Tracefile,
+}
+
+impl Reporter {
+    pub fn token(&self) -> Uuid {
+        self.token
+    }
+
+    pub fn emit(&self, event: BuildEvent) {
+        self.events.emit(&self.token, event);
+    }
+
+    pub fn info(&self, message: &str) {
+        self.trace.info(message);
+    }
+
+    pub fn error(&self, message: &str) {
+        error!("[{}] {message}", self.token);
+        self.trace.error(message);
+    }
+
+    pub fn validated(&self, file: &str, valid: bool) {
+        match valid {
+            true => self.info(&format!("Accepted '{file}' for conversion")),
+            false => self.info(&format!("Skipped '{file}': not a valid image or font")),
+        }
+        self.emit(BuildEvent::Validated {
+            file: file.to_string(),
+            valid,
+        });
+    }
+
+    pub fn queued(&self, task: &str, position: usize) {
+        self.emit(BuildEvent::Queued {
+            task: task.to_string(),
+            position,
+        });
+    }
+
+    pub async fn finish(&self, artifacts: &ArtifactResponse) {
+        match artifacts.is_empty() {
+            true => {
+                self.error("Build produced no artifacts");
+                self.emit(BuildEvent::Failed);
+            }
+            false => {
+                self.info("Artifacts are ready");
+                self.emit(BuildEvent::Ready {
+                    artifacts: artifacts.clone(),
+                });
+            }
+        }
+        self.save().await;
+    }
+
+    pub async fn cancel(&self) {
+        self.error("Build was cancelled");
+        self.emit(BuildEvent::Cancelled);
+        self.save().await;
+    }
+
+    async fn save(&self) {
+        let saved = match trace_path(&self.token) {
+            Ok(path) => self.trace.save(&path).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = saved {
+            error!("Could not save trace for {}: {e}", self.token);
+        }
+    }
+}
+
+impl Observer for Reporter {
+    fn started(&self, invocation: &Invocation) {
+        self.info(&format!("Running {invocation}"));
+        self.emit(BuildEvent::ToolStarted {
+            program: invocation.program.clone(),
+        });
+    }
+
+    fn finished(&self, invocation: &Invocation, output: &std::io::Result<Output>) {
+        metrics::get().tool(&invocation.program, invocation.elapsed());
+        let success = match output {
+            Ok(output) => {
+                let stdout = String::from_utf8_lossy(&output.stdout);
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                let message = format!(
+                    "{} exited with {}\nstdout:\n{}\nstderr:\n{}",
+                    invocation.program,
+                    output.status,
+                    stdout.trim_end(),
+                    stderr.trim_end()
+                );
+                match output.status.success() {
+                    true => self.info(&message),
+                    false => self.error(&message),
+                }
+                output.status.success()
+            }
+            Err(e) => {
+                self.error(&format!("Could not run {}: {e}", invocation.program));
+                false
+            }
+        };
+        self.emit(BuildEvent::ToolFinished {
+            program: invocation.program.clone(),
+            success,
+        });
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::SystemTime;
+
+use anyhow::Result;
+use rocket::{fairing::AdHoc, tokio};
+use uuid::Uuid;
+
+use crate::{
+    config::ArtifactsConfig, events::Events, jobs::Jobs, retention::Downloads,
+    routes::artifacts_dir,
+};
+
+#[derive(Default)]
+struct TokenUsage {
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+impl TokenUsage {
+    fn touch(&mut self, modified: SystemTime) {
+        self.modified = Some(self.modified.map_or(modified, |time| time.max(modified)));
+    }
+}
+
+pub fn directory_size(path: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.metadata() {
+            Ok(metadata) if metadata.is_dir() => directory_size(&entry.path()),
+            Ok(metadata) => metadata.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+fn token_for(path: &Path) -> Option<Uuid> {
+    let name = path.file_name()?.to_str()?;
+    let name = name.strip_suffix(".log").unwrap_or(name);
+    Uuid::parse_str(name).ok()
+}
+
+fn scan(base_dir: &Path) -> Result<HashMap<Uuid, TokenUsage>> {
+    let mut tokens: HashMap<Uuid, TokenUsage> = HashMap::new();
+    for entry in std::fs::read_dir(base_dir)?.flatten() {
+        let path = entry.path();
+        let (Some(token), Ok(metadata)) = (token_for(&path), entry.metadata()) else {
+            continue;
+        };
+        let usage = tokens.entry(token).or_default();
+        usage.size += match metadata.is_dir() {
+            true => directory_size(&path),
+            false => metadata.len(),
+        };
+        if let Ok(modified) = metadata.modified() {
+            usage.touch(modified);
+        }
+    }
+    Ok(tokens)
+}
+
+pub fn remove_token(base_dir: &Path, token: &Uuid) {
+    let directory = base_dir.join(token.to_string());
+    if directory.exists() && std::fs::remove_dir_all(&directory).is_err() {
+        error!("Could not remove artifacts for {token}");
+    }
+    let _ = std::fs::remove_file(base_dir.join(format!("{token}.log")));
+}
+
+fn expired(config: &ArtifactsConfig, tokens: &HashMap<Uuid, TokenUsage>) -> Vec<Uuid> {
+    let now = SystemTime::now();
+    let mut expired = Vec::new();
+
+    let mut remaining: Vec<_> = tokens.iter().collect();
+    remaining.retain(|(token, usage)| {
+        let age = usage
+            .modified
+            .and_then(|modified| now.duration_since(modified).ok())
+            .unwrap_or_default();
+        if age >= config.ttl() {
+            expired.push(**token);
+            return false;
+        }
+        true
+    });
+
+    let quota = config.quota.as_u64();
+    let mut total: u64 = remaining.iter().map(|(_, usage)| usage.size).sum();
+    remaining.sort_by_key(|(_, usage)| usage.modified);
+    for (token, usage) in remaining {
+        if total <= quota {
+            break;
+        }
+        total = total.saturating_sub(usage.size);
+        expired.push(*token);
+    }
+    expired
+}
+
+async fn sweep(
+    config: &ArtifactsConfig,
+    jobs: &Jobs,
+    events: &Events,
+    downloads: &Downloads,
+) -> Result<()> {
+    let base_dir = artifacts_dir()?;
+    let directory = base_dir.clone();
+    let mut tokens = tokio::task::spawn_blocking(move || scan(&directory)).await??;
+
+    for token in jobs.active().await {
+        tokens.remove(&token);
+    }
+
+    let expired = expired(config, &tokens);
+    for token in &expired {
+        remove_token(&base_dir, token);
+        jobs.remove(token).await;
+        events.remove(token);
+        downloads.remove(token);
+    }
+    if !expired.is_empty() {
+        info!("Removed {} expired artifact token(s)", expired.len());
+    }
+    Ok(())
+}
+
+pub fn fairing() -> AdHoc {
+    AdHoc::on_liftoff("Artifact Sweeper", |rocket| {
+        Box::pin(async move {
+            let (Some(config), Some(jobs), Some(events), Some(downloads)) = (
+                rocket.state::<crate::config::Config>(),
+                rocket.state::<Jobs>(),
+                rocket.state::<Events>(),
+                rocket.state::<Downloads>(),
+            ) else {
+                error!("Artifact sweeper is missing managed state");
+                return;
+            };
+            let config = config.artifacts.clone();
+            let jobs = jobs.clone();
+            let events = events.clone();
+            let downloads = downloads.clone();
+
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(config.sweep_interval());
+                loop {
+                    interval.tick().await;
+                    if let Err(e) = sweep(&config, &jobs, &events, &downloads).await {
+                        error!("Could not sweep artifacts: {e}");
+                    }
+                }
+            });
+        })
+    })
+}
@@ -0,0 +1,163 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use anyhow::Result;
+use rocket::tokio::sync::{Notify, RwLock};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{error::ApiError, response::ArtifactResponse};
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+impl JobState {
+    fn is_active(&self) -> bool {
+        matches!(self, JobState::Queued | JobState::Running)
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct Job {
+    token: Uuid,
+    state: JobState,
+    targets: BTreeMap<String, JobState>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    queue: BTreeMap<String, usize>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    errors: BTreeMap<String, ApiError>,
+    artifacts: Option<ArtifactResponse>,
+    #[serde(skip)]
+    cancel: Arc<Notify>,
+}
+
+impl Job {
+    pub fn json(&self) -> Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn targets_in(&self, state: JobState) -> impl Iterator<Item = &str> {
+        self.targets
+            .iter()
+            .filter(move |(_, target)| **target == state)
+            .map(|(name, _)| name.as_str())
+    }
+
+    fn new(token: Uuid, targets: &[String]) -> Self {
+        let targets = targets
+            .iter()
+            .map(|target| (target.clone(), JobState::Queued))
+            .collect();
+        Self {
+            token,
+            state: JobState::Queued,
+            targets,
+            queue: BTreeMap::new(),
+            errors: BTreeMap::new(),
+            artifacts: None,
+            cancel: Arc::default(),
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Jobs {
+    inner: Arc<RwLock<HashMap<Uuid, Job>>>,
+}
+
+impl Jobs {
+    pub async fn create(&self, token: Uuid, targets: &[String]) -> Job {
+        let job = Job::new(token, targets);
+        self.inner.write().await.insert(token, job.clone());
+        job
+    }
+
+    pub async fn get(&self, token: &Uuid) -> Option<Job> {
+        self.inner.read().await.get(token).cloned()
+    }
+
+    pub async fn active(&self) -> Vec<Uuid> {
+        let jobs = self.inner.read().await;
+        jobs.values()
+            .filter(|job| job.state.is_active())
+            .map(|job| job.token)
+            .collect()
+    }
+
+    pub async fn remove(&self, token: &Uuid) {
+        self.inner.write().await.remove(token);
+    }
+
+    pub async fn set_state(&self, token: &Uuid, target: &str, state: JobState) {
+        let mut jobs = self.inner.write().await;
+        if let Some(job) = jobs.get_mut(token).filter(|job| job.state.is_active()) {
+            job.state = JobState::Running;
+            job.queue.remove(target);
+            job.targets.insert(target.to_string(), state);
+        }
+    }
+
+    pub async fn set_position(&self, token: &Uuid, target: &str, position: usize) {
+        let mut jobs = self.inner.write().await;
+        if let Some(job) = jobs.get_mut(token).filter(|job| job.state.is_active()) {
+            job.queue.insert(target.to_string(), position);
+        }
+    }
+
+    pub async fn fail(&self, token: &Uuid, target: &str, error: ApiError) {
+        let mut jobs = self.inner.write().await;
+        if let Some(job) = jobs.get_mut(token).filter(|job| job.state.is_active()) {
+            job.targets.insert(target.to_string(), JobState::Failed);
+            job.errors.insert(target.to_string(), error);
+        }
+    }
+
+    pub async fn finish(&self, token: &Uuid, artifacts: ArtifactResponse) {
+        let mut jobs = self.inner.write().await;
+        if let Some(job) = jobs.get_mut(token).filter(|job| job.state.is_active()) {
+            job.state = match artifacts.is_empty() {
+                true => JobState::Failed,
+                false => JobState::Succeeded,
+            };
+            job.artifacts = Some(artifacts);
+        }
+    }
+
+    /// The SHA-256 recorded for the artifact at `path` when the job finished.
+    pub async fn digest(&self, token: &Uuid, path: &str) -> Option<String> {
+        let jobs = self.inner.read().await;
+        let artifacts = jobs.get(token)?.artifacts.as_ref()?;
+        artifacts.digest(path).map(str::to_string)
+    }
+
+    /// Resolves once the job has been cancelled, or at once if it is unknown.
+    pub async fn cancelled(&self, token: &Uuid) {
+        let cancel = match self.inner.read().await.get(token) {
+            Some(job) => job.cancel.clone(),
+            None => return,
+        };
+        cancel.notified().await;
+    }
+
+    /// Cancels a queued or running job, returning the updated job.
+    pub async fn cancel(&self, token: &Uuid) -> Option<Job> {
+        let mut jobs = self.inner.write().await;
+        let job = jobs.get_mut(token)?;
+        if job.state.is_active() {
+            job.state = JobState::Cancelled;
+            job.queue.clear();
+            for state in job.targets.values_mut().filter(|state| state.is_active()) {
+                *state = JobState::Cancelled;
+            }
+            job.cancel.notify_one();
+        }
+        Some(job.clone())
+    }
+}
@@ -1,14 +1,15 @@
 use std::fmt::Display;
 use std::io::Write;
-use std::sync::Arc;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
 
-use anyhow::{Result, bail};
+use anyhow::{Result, anyhow, bail};
 use chrono::Local;
-use rocket::tokio::sync::RwLock;
+use rocket::tokio;
 
 #[derive(Clone, Default)]
 pub struct Tracefile {
-    inner: Arc<RwLock<Vec<u8>>>,
+    inner: Arc<Mutex<Vec<u8>>>,
 }
 
 enum Level {
@@ -26,26 +27,32 @@ impl Display for Level {
 }
 
 impl Tracefile {
-    async fn log(&self, level: Level, message: &str) -> Result<()> {
+    fn log(&self, level: Level, message: &str) -> Result<()> {
         let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S%.3f").to_string();
-        let mut data = self.inner.write().await;
+        let mut data = self.inner.lock().map_err(|e| anyhow!("{e}"))?;
         writeln!(data, "[{timestamp} {level: >}] {message}")?;
         Ok(())
     }
 
-    pub async fn info(&self, message: &str) {
-        let _ = self.log(Level::Info, message).await;
+    pub fn info(&self, message: &str) {
+        let _ = self.log(Level::Info, message);
     }
 
-    pub async fn error(&self, message: &str) {
-        let _ = self.log(Level::Error, message).await;
+    pub fn error(&self, message: &str) {
+        let _ = self.log(Level::Error, message);
     }
 
-    pub async fn bytes(&self) -> Result<Vec<u8>> {
-        let data = self.inner.read().await;
+    pub fn bytes(&self) -> Result<Vec<u8>> {
+        let data = self.inner.lock().map_err(|e| anyhow!("{e}"))?;
         if data.is_empty() {
             bail!("No log data");
         }
         Ok(data.clone())
     }
+
+    pub async fn save(&self, path: &Path) -> Result<()> {
+        let bytes = self.bytes()?;
+        tokio::fs::write(path, bytes).await?;
+        Ok(())
+    }
 }
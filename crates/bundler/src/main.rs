@@ -1,11 +1,24 @@
 #[macro_use]
 extern crate rocket;
 
+mod auth;
+mod client;
+mod config;
 mod cors;
+mod download;
+mod error;
+mod events;
+mod gc;
+mod jobs;
 mod logger;
+mod metrics;
+mod pool;
+mod ratelimit;
 mod response;
+mod retention;
 mod routes;
 pub mod server;
+mod sync;
 mod tempfile;
 
 use system::programs;
@@ -13,6 +26,7 @@ use system::programs;
 use anyhow::Result;
 use log::error;
 
+use config::Config;
 use server::rocket;
 
 const CONFIG: &str = include_str!("../log4rs.yml");
@@ -22,13 +36,32 @@ async fn main() -> Result<()> {
     let config = serde_yaml::from_str(CONFIG)?;
     log4rs::init_raw_config(config)?;
 
+    let rocket = rocket();
+    let config = match rocket.figment().extract::<Config>() {
+        Ok(config) => config,
+        Err(error) => {
+            error!("Invalid configuration: {error}");
+            std::process::exit(1);
+        }
+    };
+    if let Err(error) = config.validate() {
+        error!("Invalid configuration: {error}");
+        std::process::exit(1);
+    }
+    system::config::init(config.system.clone());
+    routes::set_artifacts_dir(config.artifacts.directory.clone());
+
     if let Err(error) = programs::check_environment() {
         error!("{error}");
         std::process::exit(1);
     }
 
-    system::downloads::sync().await?;
-    rocket().launch().await?;
+    if config.system.sync_on_launch {
+        sync::sync().await?;
+    } else if let Err(error) = system::downloads::remove_stale_resources() {
+        error!("Could not remove stale resources: {error}");
+    }
+    rocket.launch().await?;
 
     Ok(())
 }
@@ -0,0 +1,256 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{LazyLock, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rocket::{
+    Request, Response,
+    fairing::{Fairing, Info, Kind},
+    http::Method,
+};
+
+use crate::{auth, response::ArtifactKind};
+
+/// Upper bounds, in seconds, of the tool duration histogram buckets.
+const DURATION_BUCKETS: [f64; 10] = [0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 300.0];
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; DURATION_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        for (bucket, bound) in self.buckets.iter_mut().zip(DURATION_BUCKETS) {
+            if value <= bound {
+                *bucket += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+#[derive(Default)]
+struct Registry {
+    requests: BTreeMap<(String, u16), u64>,
+    uploaded: BTreeMap<String, u64>,
+    builds: BTreeMap<(String, &'static str), u64>,
+    conversions: BTreeMap<&'static str, u64>,
+    tools: BTreeMap<String, Histogram>,
+    last_sync: Option<SystemTime>,
+}
+
+/// Counters and histograms exposed on `/metrics`.
+#[derive(Default)]
+pub struct Metrics {
+    inner: Mutex<Registry>,
+}
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::default);
+
+pub fn get() -> &'static Metrics {
+    &METRICS
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+impl Metrics {
+    fn registry(&self) -> MutexGuard<'_, Registry> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn request(&self, route: &str, status: u16, uploaded: u64) {
+        let mut registry = self.registry();
+        *registry
+            .requests
+            .entry((route.to_string(), status))
+            .or_default() += 1;
+        if uploaded > 0 {
+            *registry.uploaded.entry(route.to_string()).or_default() += uploaded;
+        }
+    }
+
+    /// Counts a build of `target` ending as `outcome`.
+    pub fn build(&self, target: &str, outcome: &'static str) {
+        *self
+            .registry()
+            .builds
+            .entry((target.to_string(), outcome))
+            .or_default() += 1;
+    }
+
+    pub fn converted(&self, kind: ArtifactKind) {
+        let kind = match kind {
+            ArtifactKind::Texture => "t3x",
+            ArtifactKind::Font => "bcfnt",
+            _ => return,
+        };
+        *self.registry().conversions.entry(kind).or_default() += 1;
+    }
+
+    pub fn tool(&self, program: &str, elapsed: Duration) {
+        self.registry()
+            .tools
+            .entry(program.to_string())
+            .or_default()
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn synced(&self) {
+        self.registry().last_sync = Some(SystemTime::now());
+    }
+
+    /// Renders every metric in the Prometheus text format.
+    pub fn render(&self, artifacts_bytes: u64) -> String {
+        let registry = self.registry();
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "bundler_http_requests_total",
+            "counter",
+            "Requests handled, by route and status.",
+        );
+        for ((route, status), count) in &registry.requests {
+            let route = escape(route);
+            let _ = writeln!(
+                out,
+                "bundler_http_requests_total{{route=\"{route}\",status=\"{status}\"}} {count}"
+            );
+        }
+
+        header(
+            &mut out,
+            "bundler_uploaded_bytes_total",
+            "counter",
+            "Upload bytes received, by route.",
+        );
+        for (route, bytes) in &registry.uploaded {
+            let route = escape(route);
+            let _ = writeln!(
+                out,
+                "bundler_uploaded_bytes_total{{route=\"{route}\"}} {bytes}"
+            );
+        }
+
+        header(
+            &mut out,
+            "bundler_builds_total",
+            "counter",
+            "Target builds, by platform and outcome.",
+        );
+        for ((target, outcome), count) in &registry.builds {
+            let target = escape(target);
+            let _ = writeln!(
+                out,
+                "bundler_builds_total{{target=\"{target}\",outcome=\"{outcome}\"}} {count}"
+            );
+        }
+
+        header(
+            &mut out,
+            "bundler_converted_files_total",
+            "counter",
+            "Assets converted, by output kind.",
+        );
+        for (kind, count) in &registry.conversions {
+            let _ = writeln!(
+                out,
+                "bundler_converted_files_total{{kind=\"{kind}\"}} {count}"
+            );
+        }
+
+        header(
+            &mut out,
+            "bundler_tool_duration_seconds",
+            "histogram",
+            "Time spent running each devkitPro tool.",
+        );
+        for (program, histogram) in &registry.tools {
+            let program = escape(program);
+            let name = "bundler_tool_duration_seconds";
+            for (count, bound) in histogram.buckets.iter().zip(DURATION_BUCKETS) {
+                let _ = writeln!(
+                    out,
+                    "{name}_bucket{{program=\"{program}\",le=\"{bound}\"}} {count}"
+                );
+            }
+            let (sum, count) = (histogram.sum, histogram.count);
+            let _ = writeln!(
+                out,
+                "{name}_bucket{{program=\"{program}\",le=\"+Inf\"}} {count}"
+            );
+            let _ = writeln!(out, "{name}_sum{{program=\"{program}\"}} {sum}");
+            let _ = writeln!(out, "{name}_count{{program=\"{program}\"}} {count}");
+        }
+
+        header(
+            &mut out,
+            "bundler_artifacts_bytes",
+            "gauge",
+            "Disk space used by the artifacts directory.",
+        );
+        let _ = writeln!(out, "bundler_artifacts_bytes {artifacts_bytes}");
+
+        if let Some(last_sync) = registry.last_sync {
+            let timestamp = last_sync.duration_since(UNIX_EPOCH).unwrap_or_default();
+            let age = last_sync.elapsed().unwrap_or_default();
+            header(
+                &mut out,
+                "bundler_last_sync_timestamp_seconds",
+                "gauge",
+                "Unix time of the last successful resource sync.",
+            );
+            let _ = writeln!(
+                out,
+                "bundler_last_sync_timestamp_seconds {}",
+                timestamp.as_secs()
+            );
+            header(
+                &mut out,
+                "bundler_seconds_since_last_sync",
+                "gauge",
+                "Seconds since the last successful resource sync.",
+            );
+            let _ = writeln!(out, "bundler_seconds_since_last_sync {}", age.as_secs());
+        }
+        out
+    }
+}
+
+/// Counts every response by the route that produced it.
+pub struct RequestMetrics;
+
+#[rocket::async_trait]
+impl Fairing for RequestMetrics {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request Metrics",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        if request.method() == Method::Options {
+            return;
+        }
+        let route = match request.route() {
+            Some(route) => route.uri.path().to_string(),
+            None => String::from("unmatched"),
+        };
+        get().request(&route, response.status().code, auth::received(request));
+    }
+}
@@ -0,0 +1,234 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use rocket::{
+    fairing::AdHoc,
+    tokio::{
+        select,
+        sync::{oneshot, watch},
+    },
+};
+
+use crate::{client::Client, config::Config};
+
+struct Waiter {
+    id: u64,
+    permit: oneshot::Sender<Permit>,
+    position: watch::Sender<usize>,
+}
+
+#[derive(Default)]
+struct State {
+    slots: usize,
+    available: usize,
+    next_id: u64,
+    clients: VecDeque<(String, VecDeque<Waiter>)>,
+}
+
+impl State {
+    /// Takes the first waiter of the next client in round-robin order.
+    fn next_waiter(&mut self) -> Option<Waiter> {
+        let (client, mut waiters) = self.clients.pop_front()?;
+        let waiter = waiters.pop_front();
+        if !waiters.is_empty() {
+            self.clients.push_back((client, waiters));
+        }
+        waiter
+    }
+
+    fn remove(&mut self, id: u64) {
+        for (_, waiters) in &mut self.clients {
+            waiters.retain(|waiter| waiter.id != id);
+        }
+        self.clients.retain(|(_, waiters)| !waiters.is_empty());
+    }
+
+    /// Numbers every waiter by the order in which it will be served.
+    fn update_positions(&self) {
+        let depth = self.clients.iter().map(|(_, waiters)| waiters.len());
+        let mut position = 0;
+        for round in 0..depth.max().unwrap_or(0) {
+            for waiter in self
+                .clients
+                .iter()
+                .filter_map(|(_, waiters)| waiters.get(round))
+            {
+                position += 1;
+                waiter.position.send_if_modified(|current| {
+                    let changed = *current != position;
+                    *current = position;
+                    changed
+                });
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Pool {
+    inner: Arc<Mutex<State>>,
+}
+
+impl Pool {
+    pub fn new(slots: usize) -> Self {
+        let state = State {
+            slots: slots.max(1),
+            available: slots.max(1),
+            ..State::default()
+        };
+        Self {
+            inner: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    pub fn queue(&self, client: &Client) -> Queue {
+        Queue {
+            pool: self.clone(),
+            client: client.id().to_string(),
+        }
+    }
+
+    /// Work waiting for a slot.
+    pub fn queued(&self) -> usize {
+        let state = self.state();
+        state.clients.iter().map(|(_, waiters)| waiters.len()).sum()
+    }
+
+    /// Work holding a slot.
+    pub fn running(&self) -> usize {
+        let state = self.state();
+        state.slots - state.available
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn dispatch(&self, state: &mut State) {
+        while state.available > 0 {
+            let Some(waiter) = state.next_waiter() else {
+                break;
+            };
+            let permit = Permit {
+                pool: Some(self.clone()),
+            };
+            match waiter.permit.send(permit) {
+                Ok(()) => state.available -= 1,
+                Err(mut permit) => permit.pool = None,
+            }
+        }
+        state.update_positions();
+    }
+
+    fn release(&self) {
+        let mut state = self.state();
+        state.available += 1;
+        self.dispatch(&mut state);
+    }
+}
+
+/// A client's view of the pool, so its work is served in turn with others.
+#[derive(Clone)]
+pub struct Queue {
+    pool: Pool,
+    client: String,
+}
+
+impl Queue {
+    pub fn enqueue(&self) -> Ticket {
+        let (permit, receiver) = oneshot::channel();
+        let (position, watcher) = watch::channel(0);
+
+        let mut state = self.pool.state();
+        let id = state.next_id;
+        state.next_id += 1;
+
+        let waiter = Waiter {
+            id,
+            permit,
+            position,
+        };
+        match state
+            .clients
+            .iter_mut()
+            .find(|(client, _)| *client == self.client)
+        {
+            Some((_, waiters)) => waiters.push_back(waiter),
+            None => state
+                .clients
+                .push_back((self.client.clone(), VecDeque::from([waiter]))),
+        }
+        self.pool.dispatch(&mut state);
+
+        Ticket {
+            id,
+            pool: self.pool.clone(),
+            permit: receiver,
+            position: watcher,
+        }
+    }
+}
+
+/// A place in the queue, given up when dropped.
+pub struct Ticket {
+    id: u64,
+    pool: Pool,
+    permit: oneshot::Receiver<Permit>,
+    position: watch::Receiver<usize>,
+}
+
+impl Ticket {
+    /// Waits for a worker slot, reporting every change of the 1-based queue position.
+    pub async fn wait<F, Fut>(mut self, mut on_position: F) -> Permit
+    where
+        F: FnMut(usize) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        loop {
+            select! {
+                biased;
+                permit = &mut self.permit => return permit.unwrap_or(Permit { pool: None }),
+                changed = self.position.changed() => {
+                    if changed.is_err() {
+                        let permit = (&mut self.permit).await;
+                        return permit.unwrap_or(Permit { pool: None });
+                    }
+                    let position = *self.position.borrow_and_update();
+                    on_position(position).await;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        let mut state = self.pool.state();
+        state.remove(self.id);
+        state.update_positions();
+    }
+}
+
+/// A worker slot, returned to the pool when dropped.
+pub struct Permit {
+    pool: Option<Pool>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool.take() {
+            pool.release();
+        }
+    }
+}
+
+pub fn fairing() -> AdHoc {
+    AdHoc::on_ignite("Worker Pool", |rocket| async move {
+        let slots = rocket
+            .state::<Config>()
+            .map(|config| config.pool.slots())
+            .unwrap_or(1);
+        rocket.manage(Pool::new(slots))
+    })
+}
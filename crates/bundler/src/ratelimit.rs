@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use rocket::{
+    Build, Data, Request, Response, Rocket,
+    fairing::{self, Fairing, Info, Kind},
+    http::{Header, Method, Status, uri::Origin},
+    request::{FromRequest, Outcome},
+};
+
+use log::warn;
+
+use crate::client::{Network, TrustedProxies};
+use crate::config::{Config, RateLimitRoute};
+use crate::error::ApiError;
+
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Where requests over their budget are rerouted, so a route answers them.
+const RATE_LIMITED_PATH: &str = "/__ratelimited";
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// The outcome of taking a token, reported back in the `RateLimit-*` headers.
+#[derive(Clone, Copy)]
+struct Decision {
+    limit: u32,
+    remaining: u32,
+    reset: u64,
+    retry_after: Option<u64>,
+}
+
+struct Buckets {
+    buckets: HashMap<(usize, IpAddr), Bucket>,
+    pruned: Instant,
+}
+
+/// Token buckets for each route budget and client address.
+pub struct RateLimiter {
+    routes: Vec<RateLimitRoute>,
+    state: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    fn new(routes: Vec<RateLimitRoute>) -> Self {
+        Self {
+            routes,
+            state: Mutex::new(Buckets {
+                buckets: HashMap::new(),
+                pruned: Instant::now(),
+            }),
+        }
+    }
+
+    fn route(&self, path: &str) -> Option<usize> {
+        self.routes.iter().position(|route| {
+            path.strip_prefix(&route.path)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+    }
+
+    fn take(&self, index: usize, ip: IpAddr) -> Decision {
+        let route = &self.routes[index];
+        let capacity = f64::from(route.requests);
+        let rate = capacity / route.period as f64;
+        let now = Instant::now();
+
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if now.duration_since(state.pruned) >= PRUNE_INTERVAL {
+            let routes = &self.routes;
+            state.buckets.retain(|(index, _), bucket| {
+                now.duration_since(bucket.updated).as_secs() < routes[*index].period
+            });
+            state.pruned = now;
+        }
+
+        let bucket = state.buckets.entry((index, ip)).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(capacity);
+        bucket.updated = now;
+
+        let retry_after = match bucket.tokens >= 1.0 {
+            true => {
+                bucket.tokens -= 1.0;
+                None
+            }
+            false => Some(((1.0 - bucket.tokens) / rate).ceil() as u64),
+        };
+        Decision {
+            limit: route.requests,
+            remaining: bucket.tokens.floor() as u32,
+            reset: ((capacity - bucket.tokens) / rate).ceil() as u64,
+            retry_after,
+        }
+    }
+}
+
+/// The decision made for a request, if a budget applied to it.
+struct Limited(Option<Decision>);
+
+/// The path a request was made to, kept when it is rerouted.
+struct Requested(Option<String>);
+
+/// The path the client asked for, even if the request was rerouted since.
+pub fn requested_path<'r>(request: &'r Request<'_>) -> &'r str {
+    match &request.local_cache(|| Requested(None)).0 {
+        Some(path) => path,
+        None => request.uri().path().as_str(),
+    }
+}
+
+/// Seconds a rate-limited client has to wait, for requests over their budget.
+pub struct Throttled(u64);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Throttled {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match request
+            .local_cache(|| Limited(None))
+            .0
+            .and_then(|decision| decision.retry_after)
+        {
+            Some(seconds) => Outcome::Success(Throttled(seconds)),
+            None => Outcome::Forward(Status::NotFound),
+        }
+    }
+}
+
+#[get("/__ratelimited")]
+pub fn rate_limited(throttled: Throttled) -> ApiError {
+    ApiError::new(
+        Status::TooManyRequests,
+        "rate_limited",
+        format!("too many requests, retry in {}s", throttled.0),
+    )
+}
+
+/// Applies the `rate_limit` budgets to each client address.
+pub struct RateLimit;
+
+#[rocket::async_trait]
+impl Fairing for RateLimit {
+    fn info(&self) -> Info {
+        Info {
+            name: "Rate Limit",
+            kind: Kind::Ignite | Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        let config = rocket
+            .state::<Config>()
+            .map(|config| config.rate_limit.clone())
+            .unwrap_or_default();
+        let networks = config
+            .trusted_proxies
+            .iter()
+            .filter_map(|proxy| Network::from_str(proxy).ok())
+            .collect();
+        let rocket = rocket.manage(TrustedProxies::new(networks));
+        match config.enabled {
+            true => Ok(rocket
+                .manage(RateLimiter::new(config.routes))
+                .mount("/", routes![rate_limited])),
+            false => Ok(rocket),
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        if request.method() == Method::Options {
+            return;
+        }
+        let (Some(limiter), Some(proxies)) = (
+            request.rocket().state::<RateLimiter>(),
+            request.rocket().state::<TrustedProxies>(),
+        ) else {
+            return;
+        };
+        let Some(index) = limiter.route(request.uri().path().as_str()) else {
+            return;
+        };
+        let Some(ip) = proxies.client_ip(request) else {
+            return;
+        };
+
+        let decision = limiter.take(index, ip);
+        request.local_cache(|| Limited(Some(decision)));
+        if decision.retry_after.is_some() {
+            let path = request.uri().path().to_string();
+            warn!("Rate limited {ip} on {path}");
+            // Fairings cannot answer a request themselves, so hand it to the
+            // route that responds with the rejection.
+            request.local_cache(|| Requested(Some(path)));
+            request.set_method(Method::Get);
+            request.set_uri(Origin::path_only(RATE_LIMITED_PATH));
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let Some(decision) = request.local_cache(|| Limited(None)).0 else {
+            return;
+        };
+        response.set_header(Header::new("RateLimit-Limit", decision.limit.to_string()));
+        response.set_header(Header::new(
+            "RateLimit-Remaining",
+            decision.remaining.to_string(),
+        ));
+        response.set_header(Header::new("RateLimit-Reset", decision.reset.to_string()));
+        if let Some(seconds) = decision.retry_after {
+            response.set_header(Header::new("Retry-After", seconds.to_string()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use super::RateLimiter;
+    use crate::config::RateLimitRoute;
+
+    const CLIENT: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+    const OTHER: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2));
+
+    fn limiter() -> RateLimiter {
+        RateLimiter::new(vec![
+            RateLimitRoute {
+                path: String::from("/compile"),
+                requests: 3,
+                period: 3600,
+            },
+            RateLimitRoute {
+                path: String::from("/convert"),
+                requests: 1,
+                period: 60,
+            },
+        ])
+    }
+
+    #[test]
+    fn budget_runs_out_after_its_requests() {
+        let limiter = limiter();
+        for remaining in [2, 1, 0] {
+            let decision = limiter.take(0, CLIENT);
+            assert_eq!(decision.limit, 3);
+            assert_eq!(decision.remaining, remaining);
+            assert!(decision.retry_after.is_none());
+        }
+        let decision = limiter.take(0, CLIENT);
+        assert_eq!(decision.remaining, 0);
+        assert_eq!(decision.retry_after, Some(1200));
+        assert_eq!(decision.reset, 3600);
+    }
+
+    #[test]
+    fn budgets_are_kept_per_route_and_address() {
+        let limiter = limiter();
+        assert!(limiter.take(1, CLIENT).retry_after.is_none());
+        assert_eq!(limiter.take(1, CLIENT).retry_after, Some(60));
+        assert!(limiter.take(1, OTHER).retry_after.is_none());
+        assert!(limiter.take(0, CLIENT).retry_after.is_none());
+    }
+
+    #[test]
+    fn routes_match_whole_path_segments() {
+        let limiter = limiter();
+        assert_eq!(limiter.route("/compile"), Some(0));
+        assert_eq!(limiter.route("/compile/extra"), Some(0));
+        assert_eq!(limiter.route("/convert"), Some(1));
+        assert_eq!(limiter.route("/compiler"), None);
+        assert_eq!(limiter.route("/bundle"), None);
+    }
+}
@@ -1,13 +1,75 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::Result;
+use rocket::{
+    http::Header,
+    tokio::{self, fs::File},
+};
 use serde::Serialize;
+use sha2::{Digest, Sha256};
+use system::platform::Platform;
 use uuid::Uuid;
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ArtifactKind {
+    Ctr,
+    Hac,
+    Cafe,
+    Texture,
+    Font,
+}
+
+impl ArtifactKind {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ArtifactKind::Ctr => "application/x-3dsx",
+            ArtifactKind::Hac => "application/x-nro",
+            ArtifactKind::Cafe => "application/x-wuhb",
+            ArtifactKind::Texture => "application/x-t3x",
+            ArtifactKind::Font => "application/x-bcfnt",
+        }
+    }
+
+    /// The kind of the artifact at `path`, from the extension it was written with.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "3dsx" => Some(ArtifactKind::Ctr),
+            "nro" => Some(ArtifactKind::Hac),
+            "wuhb" => Some(ArtifactKind::Cafe),
+            "t3x" => Some(ArtifactKind::Texture),
+            "bcfnt" => Some(ArtifactKind::Font),
+            _ => None,
+        }
+    }
+}
+
+impl From<&Platform> for ArtifactKind {
+    fn from(platform: &Platform) -> Self {
+        match platform {
+            Platform::Ctr => ArtifactKind::Ctr,
+            Platform::Hac => ArtifactKind::Hac,
+            Platform::Cafe => ArtifactKind::Cafe,
+        }
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct ArtifactFile {
+    path: String,
+    size: u64,
+    sha256: String,
+    content_type: &'static str,
+    kind: ArtifactKind,
+}
+
+#[derive(Serialize, Clone)]
 pub struct ArtifactResponse {
-    files: Vec<String>,
+    files: Vec<ArtifactFile>,
     token: Uuid,
+    /// LÖVE Potion release the binaries were built with.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lovepotion_version: Option<String>,
 }
 
 impl ArtifactResponse {
@@ -15,15 +77,64 @@ impl ArtifactResponse {
         Self {
             files: Vec::new(),
             token,
+            lovepotion_version: None,
         }
     }
 
-    pub fn add_file(&mut self, filepath: PathBuf) {
-        let filepath = filepath.to_string_lossy().replace("\\", "/");
-        self.files.push(filepath);
+    pub fn with_version(mut self, version: Option<String>) -> Self {
+        self.lovepotion_version = version;
+        self
+    }
+
+    pub async fn add_file(
+        &mut self,
+        directory: &Path,
+        filepath: PathBuf,
+        kind: ArtifactKind,
+    ) -> Result<()> {
+        let bytes = tokio::fs::read(directory.join(&filepath)).await?;
+        let sha256 = format!("{:x}", Sha256::digest(&bytes));
+        let path = filepath.to_string_lossy().replace("\\", "/");
+        self.files.push(ArtifactFile {
+            path,
+            size: bytes.len() as u64,
+            sha256,
+            content_type: kind.content_type(),
+            kind,
+        });
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
     }
 
     pub fn json(&self) -> Result<String> {
         Ok(serde_json::to_string(self)?)
     }
+
+    /// The SHA-256 recorded for the artifact at `path`.
+    pub fn digest(&self, path: &str) -> Option<&str> {
+        self.files
+            .iter()
+            .find(|file| file.path == path)
+            .map(|file| file.sha256.as_str())
+    }
+}
+
+#[derive(Responder)]
+#[response(content_type = "application/zip")]
+pub struct ArchiveResponse {
+    data: File,
+    disposition: Header<'static>,
+}
+
+impl ArchiveResponse {
+    pub fn new(token: Uuid, data: File) -> Self {
+        let disposition = format!("attachment; filename=\"{token}.zip\"");
+        Self {
+            data,
+            disposition: Header::new("Content-Disposition", disposition),
+        }
+    }
 }
@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use uuid::Uuid;
+
+use crate::config::RetentionConfig;
+
+#[derive(Clone, Default)]
+pub struct Downloads {
+    inner: Arc<Mutex<HashMap<Uuid, HashMap<String, u32>>>>,
+}
+
+impl Downloads {
+    pub fn record(&self, token: &Uuid, filepath: &str, config: &RetentionConfig) -> bool {
+        let Ok(mut downloads) = self.inner.lock() else {
+            return false;
+        };
+        let count = downloads
+            .entry(*token)
+            .or_default()
+            .entry(filepath.to_string())
+            .or_default();
+        *count += 1;
+        config.downloads > 0 && *count >= config.downloads
+    }
+
+    pub fn remove(&self, token: &Uuid) {
+        if let Ok(mut downloads) = self.inner.lock() {
+            downloads.remove(token);
+        }
+    }
+}
+
+pub fn window_elapsed(config: &RetentionConfig, directory: &Path) -> bool {
+    if config.window == 0 {
+        return false;
+    }
+    let Ok(metadata) = std::fs::metadata(directory) else {
+        return false;
+    };
+    let Ok(created) = metadata.created().or_else(|_| metadata.modified()) else {
+        return false;
+    };
+    SystemTime::now()
+        .duration_since(created)
+        .is_ok_and(|age| age >= config.window())
+}
+
+pub fn files(directory: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut directories = vec![directory.to_path_buf()];
+    while let Some(current) = directories.pop() {
+        let Ok(entries) = std::fs::read_dir(&current) else {
+            continue;
+        };
+        for path in entries.flatten().map(|entry| entry.path()) {
+            if path.is_dir() {
+                directories.push(path);
+            } else if let Ok(relative) = path.strip_prefix(directory) {
+                files.push(relative.to_path_buf());
+            }
+        }
+    }
+    files
+}
@@ -0,0 +1,18 @@
+use rocket::{State, http::Status};
+
+use crate::{auth::Admin, error::ApiError, sync::Syncer};
+
+#[post("/admin/sync")]
+pub async fn trigger_sync(
+    _admin: Admin,
+    syncer: &State<Syncer>,
+) -> Result<(Status, String), ApiError> {
+    if !syncer.trigger() {
+        return Err(ApiError::new(
+            Status::Conflict,
+            "sync_in_progress",
+            "a resource sync is already running",
+        ));
+    }
+    Ok((Status::Accepted, String::from(r#"{"status":"started"}"#)))
+}
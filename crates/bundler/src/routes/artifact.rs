@@ -1,39 +1,190 @@
-use std::path::{Component, PathBuf};
+use std::io::Seek;
+use std::path::{Component, Path, PathBuf};
 
-use rocket::{fs::NamedFile, http::Status, tokio};
+use asset::archive::Archive;
+use rocket::{
+    State,
+    fs::NamedFile,
+    http::{ContentType, Status},
+    tokio,
+};
 use uuid::Uuid;
 
-use crate::routes::artifacts_dir;
+use crate::{
+    auth::{ApiKeys, Caller},
+    config::Config,
+    download::{self, Conditions, Download},
+    error::ApiError,
+    gc,
+    jobs::Jobs,
+    response::{ArchiveResponse, ArtifactKind},
+    retention::{self, Downloads, window_elapsed},
+    routes::{artifacts_dir, parse_token, trace_path},
+};
 
-fn check_is_empty(path: &PathBuf) -> bool {
-    path.read_dir()
-        .map_or(false, |mut dir| dir.next().is_none())
+fn check_is_empty(path: &Path) -> bool {
+    path.read_dir().is_ok_and(|mut dir| dir.next().is_none())
 }
 
-#[get("/artifact?<uuid>&<filepath..>")]
-pub async fn artifact(uuid: String, filepath: String) -> Result<NamedFile, Status> {
-    let base_path = artifacts_dir().map_err(|_| Status::InternalServerError)?;
-    if Uuid::parse_str(&uuid).is_err() || filepath.is_empty() {
-        return Err(Status::BadRequest);
-    }
-    let artifact_path = base_path.join(&uuid);
-    let path = PathBuf::from(filepath);
-    if path.components().any(|c| matches!(c, Component::ParentDir)) {
-        return Err(Status::Forbidden);
+fn remove_file(artifact_path: &Path, filepath: &Path) -> Result<(), ApiError> {
+    std::fs::remove_file(artifact_path.join(filepath)).map_err(ApiError::internal)?;
+    if check_is_empty(artifact_path) {
+        std::fs::remove_dir_all(artifact_path).map_err(ApiError::internal)?;
     }
+    Ok(())
+}
 
-    let path = artifact_path.join(path);
-    let file = NamedFile::open(&path).await.map_err(|_| Status::NotFound)?;
+fn expired_error(token: &Uuid) -> ApiError {
+    ApiError::new(
+        Status::Gone,
+        "expired",
+        format!("artifacts for {token} have expired"),
+    )
+}
 
-    if let Err(_) = tokio::fs::remove_file(&path).await {
-        return Err(Status::InternalServerError);
+fn expire(base_path: &Path, token: &Uuid, config: &Config, downloads: &Downloads) -> bool {
+    if !window_elapsed(&config.retention, &base_path.join(token.to_string())) {
+        return false;
     }
+    gc::remove_token(base_path, token);
+    downloads.remove(token);
+    true
+}
 
-    if check_is_empty(&artifact_path) {
-        if let Err(_) = tokio::fs::remove_dir_all(&artifact_path).await {
-            return Err(Status::InternalServerError);
+#[get("/artifact?<uuid>&<filepath..>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn artifact(
+    uuid: String,
+    filepath: String,
+    conditions: Conditions<'_>,
+    caller: Caller,
+    keys: &State<ApiKeys>,
+    config: &State<Config>,
+    downloads: &State<Downloads>,
+    jobs: &State<Jobs>,
+) -> Result<Download, ApiError> {
+    let token = parse_token(&uuid)?;
+    let download: Result<Download, ApiError> = async {
+        let base_path = artifacts_dir().map_err(ApiError::internal)?;
+        if filepath.is_empty() {
+            return Err(
+                ApiError::bad_request("missing_field", "no file path was given")
+                    .with_field("filepath"),
+            );
+        }
+        let artifact_path = base_path.join(token.to_string());
+        let path = PathBuf::from(&filepath);
+        if !path.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(ApiError::new(
+                Status::Forbidden,
+                "forbidden_path",
+                "path must be relative to the artifact",
+            )
+            .with_file(&filepath));
+        }
+
+        if expire(&base_path, &token, config, downloads) {
+            return Err(expired_error(&token));
+        }
+
+        let file_path = artifact_path.join(&path);
+        let unknown =
+            || ApiError::not_found("unknown_artifact", "no such artifact").with_file(&filepath);
+        let metadata = tokio::fs::metadata(&file_path)
+            .await
+            .ok()
+            .filter(|metadata| metadata.is_file())
+            .ok_or_else(unknown)?;
+        let content_type = ArtifactKind::from_path(&file_path)
+            .and_then(|kind| ContentType::parse_flexible(kind.content_type()))
+            .unwrap_or(ContentType::Binary);
+
+        let etag = match jobs.digest(&token, &filepath).await {
+            Some(digest) => download::etag(&digest),
+            None => download::weak_etag(&metadata),
+        };
+        if conditions.matches(&etag) {
+            return Ok(Download::not_modified(etag, content_type));
+        }
+        let file = tokio::fs::File::open(&file_path)
+            .await
+            .map_err(|_| unknown())?;
+        let download = Download::new(file, etag, content_type, &conditions)
+            .await
+            .map_err(ApiError::internal)?;
+        if let Some(name) = caller.key() {
+            keys.record_bytes(name, download.body_len());
         }
+        if download.is_complete() && downloads.record(&token, &filepath, &config.retention) {
+            remove_file(&artifact_path, &path)?;
+        }
+
+        Ok(download)
     }
+    .await;
+    download.map_err(|e| e.with_token(token))
+}
 
-    Ok(file)
+#[get("/artifact/<uuid>/log")]
+pub async fn artifact_log(
+    uuid: &str,
+    _caller: Caller,
+) -> Result<(ContentType, NamedFile), ApiError> {
+    let token = parse_token(uuid)?;
+    let path = trace_path(&token).map_err(|e| ApiError::internal(e).with_token(token))?;
+    let file = NamedFile::open(path).await.map_err(|_| {
+        ApiError::not_found("unknown_token", format!("no build log for {token}")).with_token(token)
+    })?;
+    Ok((ContentType::Plain, file))
+}
+
+#[get("/artifact/<uuid>/zip")]
+pub async fn artifact_zip(
+    uuid: &str,
+    caller: Caller,
+    keys: &State<ApiKeys>,
+    config: &State<Config>,
+    downloads: &State<Downloads>,
+) -> Result<ArchiveResponse, ApiError> {
+    let token = parse_token(uuid)?;
+    let archive: Result<ArchiveResponse, ApiError> = async {
+        let base_path = artifacts_dir().map_err(ApiError::internal)?;
+        let artifact_path = base_path.join(token.to_string());
+        if expire(&base_path, &token, config, downloads) {
+            return Err(expired_error(&token));
+        }
+        if !artifact_path.is_dir() || check_is_empty(&artifact_path) {
+            return Err(ApiError::not_found(
+                "unknown_token",
+                format!("no artifacts for {token}"),
+            ));
+        }
+
+        // The zip is written to an unnamed temporary file and streamed from
+        // there, so a large artifact is never held in memory.
+        let path = artifact_path.clone();
+        let data = tokio::task::spawn_blocking(move || {
+            let mut file = Archive::create(&path, ::tempfile::tempfile()?)?;
+            file.rewind()?;
+            Ok::<_, system::error::Error>(file)
+        })
+        .await
+        .map_err(ApiError::internal)?
+        .map_err(ApiError::from)?;
+        if let Some(name) = caller.key() {
+            let size = data.metadata().map_err(ApiError::internal)?.len();
+            keys.record_bytes(name, size);
+        }
+
+        for filepath in retention::files(&artifact_path) {
+            let name = filepath.to_string_lossy().replace("\\", "/");
+            if downloads.record(&token, &name, &config.retention) {
+                remove_file(&artifact_path, &filepath)?;
+            }
+        }
+
+        Ok(ArchiveResponse::new(token, tokio::fs::File::from_std(data)))
+    }
+    .await;
+    archive.map_err(|e| e.with_token(token))
 }
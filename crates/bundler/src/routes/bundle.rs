@@ -0,0 +1,42 @@
+use rocket::{
+    State,
+    form::{Form, FromForm},
+    fs::TempFile,
+};
+
+use crate::{
+    auth::Uploads,
+    client::Client,
+    config::Config,
+    error::ApiError,
+    events::Events,
+    jobs::Jobs,
+    pool::Pool,
+    routes::compile::{BuildUpload, start_build},
+};
+
+#[derive(FromForm)]
+pub struct BundleRequest<'f> {
+    pub config: String,
+    pub game: TempFile<'f>,
+    pub icon: Option<TempFile<'f>>,
+}
+
+#[post("/bundle", format = "multipart/form-data", data = "<form>")]
+pub async fn bundle(
+    form: Form<BundleRequest<'_>>,
+    client: Client,
+    uploads: Uploads<'_>,
+    pool: &State<Pool>,
+    jobs: &State<Jobs>,
+    events: &State<Events>,
+    config: &State<Config>,
+) -> Result<String, ApiError> {
+    let upload = BuildUpload {
+        config: &form.config,
+        icon: &form.icon,
+        game: Some(&form.game),
+        limits: config.archive.limits(),
+    };
+    start_build(upload, &uploads, pool.queue(&client), jobs, events).await
+}
@@ -1,91 +1,380 @@
-use std::str::FromStr;
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
 
-use anyhow::Result;
-use asset::icon::Icon;
-use binary::{cafe::Cafe, compile::Compile, ctr::Ctr, hac::Hac, metadata::Metadata};
+use asset::{
+    archive::{Archive, Limits},
+    icon::Icon,
+};
+use binary::{
+    cafe::Cafe, compile::Compile, ctr::Ctr, hac::Hac, metadata::Metadata, romfs::copy_dir,
+};
 use rocket::{
+    State,
     form::{Form, FromForm},
     fs::TempFile,
     futures::future::join_all,
-    http::Status,
-    tokio,
+    tokio::{self, select},
+};
+use system::{
+    error::Result,
+    platform::Platform,
+    resources::{self, ResourceSet},
 };
-use system::{platform::Platform, resources};
 use uuid::Uuid;
 
-use crate::{response::ArtifactResponse, routes::artifacts_dir, tempfile::TempFileExt};
+use crate::{
+    auth::Uploads,
+    client::Client,
+    config::Config,
+    error::ApiError,
+    events::{Events, Reporter},
+    jobs::{JobState, Jobs},
+    metrics,
+    pool::{Pool, Queue},
+    response::{ArtifactKind, ArtifactResponse},
+    routes::{artifacts_dir, convert::processor_for},
+    tempfile::TempFileExt,
+};
 
 #[derive(FromForm, Debug)]
 pub struct CompileRequest<'f> {
     pub config: String,
     pub icon: Option<TempFile<'f>>,
+    pub game: Option<TempFile<'f>>,
 }
 
-#[post("/compile", data = "<form>")]
-pub async fn compile(form: Form<CompileRequest<'_>>) -> Result<String, Status> {
-    let mut metadata = match serde_json::from_str::<Metadata>(&form.config) {
-        Ok(metadata) => metadata,
-        Err(_) => return Err(Status::BadRequest),
-    };
-    metadata.targets.dedup();
+/// Directory in a job, and in each target, holding the extracted game.
+const GAME_DIRECTORY: &str = "game";
 
-    let base_dir = artifacts_dir().map_err(|_| Status::InternalServerError)?;
+/// An uploaded game archive and the limits its extraction must stay within.
+struct GameUpload {
+    bytes: Vec<u8>,
+    limits: Limits,
+}
 
-    let token = Uuid::new_v4();
-    let directory = base_dir.join(token.to_string());
-    if let Err(e) = tokio::fs::create_dir_all(&directory).await {
-        error!("Could not generate directory: {e}");
-        return Err(Status::InternalServerError);
+/// A game extracted once per job and shared by its targets.
+pub struct Game {
+    directory: PathBuf,
+    files: Vec<PathBuf>,
+}
+
+/// Extensions of the game files that may be images or fonts to convert.
+const CONVERTIBLE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "ttf", "otf"];
+
+fn is_convertible(file: &Path) -> bool {
+    file.extension().is_some_and(|extension| {
+        CONVERTIBLE_EXTENSIONS
+            .iter()
+            .any(|candidate| extension.eq_ignore_ascii_case(candidate))
+    })
+}
+
+/// Converts the game's images and fonts in place. Files that are not valid
+/// images or fonts are kept as they are, while a file that cannot be read or
+/// converted fails the target rather than shipping unconverted.
+async fn convert_assets(game_dir: &Path, files: &[PathBuf], reporter: &Reporter) -> Result<()> {
+    for file in files.iter().filter(|file| is_convertible(file)) {
+        let display = file.to_string_lossy();
+        let file_path = game_dir.join(file);
+        let bytes = tokio::fs::read(&file_path).await.inspect_err(|e| {
+            reporter.error(&format!("Could not read '{display}': {e}"));
+        })?;
+        let asset = tokio::task::spawn_blocking(move || processor_for(&bytes))
+            .await
+            .map_err(std::io::Error::other)?;
+        reporter.validated(&display, asset.is_some());
+        let Some((asset, kind)) = asset else {
+            continue;
+        };
+        let (Some(parent), Some(file_name)) = (file_path.parent(), file_path.file_name()) else {
+            continue;
+        };
+        match asset.process(parent, Path::new(file_name), reporter).await {
+            Ok(_) => metrics::get().converted(kind),
+            Err(e) => {
+                reporter.error(&format!("Could not convert '{display}': {e}"));
+                return Err(e);
+            }
+        }
     }
+    Ok(())
+}
+
+async fn extract_game(directory: &Path, upload: GameUpload) -> Result<Game> {
+    let game_dir = directory.join(GAME_DIRECTORY);
+    let destination = game_dir.clone();
+    let files = tokio::task::spawn_blocking(move || {
+        Archive::extract(&upload.bytes, &destination, upload.limits)
+    })
+    .await
+    .map_err(std::io::Error::other)??;
+    Ok(Game {
+        directory: game_dir,
+        files,
+    })
+}
 
-    let icon_bytes = match &form.icon {
-        Some(icon) if icon.len() > 0 => icon.read_bytes().await,
-        _ => tokio::fs::read(resources::fetch_icon()).await,
+pub async fn compile_target(
+    directory: PathBuf,
+    target: String,
+    metadata: Metadata,
+    icon_bytes: Vec<u8>,
+    game: Option<Arc<Game>>,
+    reporter: Reporter,
+) -> Result<(PathBuf, ArtifactKind)> {
+    let platform = Platform::from_str(&target)?;
+    reporter.info(&format!("Compiling target '{platform}'"));
+    let target_path = directory.join(platform.to_string());
+    tokio::fs::create_dir_all(&target_path).await?;
+
+    // 3DS assets are converted in place, so that target works on its own copy.
+    let game_dir = target_path.join(GAME_DIRECTORY);
+    let copied = game.is_some() && platform == Platform::Ctr;
+    if let Some(game) = game.as_ref().filter(|_| copied) {
+        let (from, to) = (game.directory.clone(), game_dir.clone());
+        tokio::task::spawn_blocking(move || copy_dir(&from, &to))
+            .await
+            .map_err(std::io::Error::other)?
+            .inspect_err(|e| reporter.error(&format!("Could not copy the game: {e}")))?;
+        if let Err(e) = convert_assets(&game_dir, &game.files, &reporter).await {
+            let _ = tokio::fs::remove_dir_all(&game_dir).await;
+            return Err(e);
+        }
     }
-    .map_err(|_| Status::InternalServerError)?;
+    let game = match &game {
+        Some(_) if copied => Some(game_dir.as_path()),
+        Some(game) => Some(game.directory.as_path()),
+        None => None,
+    };
+    let icon_path = target_path.join("icon.bin");
+    let (path, target) = (icon_path.clone(), platform.clone());
+    let icon =
+        tokio::task::spawn_blocking(move || Icon::from_bytes(&target, &icon_bytes)?.create(&path))
+            .await
+            .unwrap_or_else(|e| Err(std::io::Error::other(e).into()));
+    let binary: Box<dyn Compile> = match platform {
+        Platform::Ctr => Box::new(Ctr {}),
+        Platform::Hac => Box::new(Hac {}),
+        Platform::Cafe => Box::new(Cafe {}),
+    };
+    let result = match icon {
+        Ok(()) => {
+            binary
+                .compile(&target_path, &metadata, &icon_path, game, &reporter)
+                .await
+        }
+        Err(e) => Err(e),
+    };
+    let _ = tokio::fs::remove_file(icon_path).await;
+    if copied {
+        let _ = tokio::fs::remove_dir_all(&game_dir).await;
+    }
+
+    let path = result?;
+    let path = path.strip_prefix(&directory).unwrap_or(&path).to_owned();
+    Ok((path, ArtifactKind::from(&platform)))
+}
 
+async fn run_job(
+    jobs: Jobs,
+    queue: Queue,
+    reporter: Reporter,
+    directory: PathBuf,
+    metadata: Metadata,
+    icon_bytes: Vec<u8>,
+    game: Option<GameUpload>,
+) {
+    let token = reporter.token();
+    let game = match game {
+        Some(upload) => match extract_game(&directory, upload).await {
+            Ok(game) => Some(Arc::new(game)),
+            Err(e) => {
+                reporter.error(&format!("Could not extract the game: {e}"));
+                let error = ApiError::from(e);
+                for target in &metadata.targets {
+                    metrics::get().build(target, "failed");
+                    jobs.fail(&token, target, error.clone()).await;
+                }
+                let response = ArtifactResponse::new(token);
+                reporter.finish(&response).await;
+                jobs.finish(&token, response).await;
+                return;
+            }
+        },
+        None => None,
+    };
     let tasks = metadata.targets.clone().into_iter().map(|target| {
-        let metadata = metadata.clone();
-        let icon_bytes = icon_bytes.clone();
-        let directory = directory.clone();
+        let jobs = jobs.clone();
+        let ticket = queue.enqueue();
+        let reporter = reporter.clone();
+        let task = compile_target(
+            directory.clone(),
+            target.clone(),
+            metadata.clone(),
+            icon_bytes.clone(),
+            game.clone(),
+            reporter.clone(),
+        );
         async move {
-            let platform = Platform::from_str(&target).ok()?;
-            let target_path = directory.join(target);
-            if !target_path.exists() {
-                tokio::fs::create_dir_all(&target_path).await.ok()?;
-            }
-            let icon_path = target_path.join("icon.bin");
-            let _ = Icon::from_bytes(&platform, &icon_bytes)?.create(&icon_path);
-            let binary: Box<dyn Compile + Send> = match platform {
-                Platform::Ctr => Box::new(Ctr {}),
-                Platform::Hac => Box::new(Hac {}),
-                Platform::Cafe => Box::new(Cafe {}),
-            };
-            let result = binary.compile(&target_path, &metadata, &icon_path);
-            tokio::fs::remove_file(icon_path).await.ok()?;
-
-            if let Err(result) = result {
-                println!("{result:?}");
-            } else if let Ok(path) = result {
-                let path = path.strip_prefix(directory).ok()?;
-                return Some(path.to_owned());
+            let _permit = ticket
+                .wait(|position| {
+                    reporter.queued(&target, position);
+                    jobs.set_position(&token, &target, position)
+                })
+                .await;
+            jobs.set_state(&token, &target, JobState::Running).await;
+            match task.await {
+                Ok(result) => {
+                    metrics::get().build(&target, "succeeded");
+                    jobs.set_state(&token, &target, JobState::Succeeded).await;
+                    Some(result)
+                }
+                Err(e) => {
+                    reporter.error(&format!("Could not compile '{target}': {e}"));
+                    metrics::get().build(&target, "failed");
+                    jobs.fail(&token, &target, ApiError::from(e)).await;
+                    None
+                }
             }
-            None
         }
     });
 
-    let results: Vec<_> = join_all(tasks).await.into_iter().flatten().collect();
-    if results.is_empty() {
-        return Err(Status::BadRequest);
+    let results: Option<Vec<_>> = select! {
+        results = join_all(tasks) => Some(results.into_iter().flatten().collect()),
+        _ = jobs.cancelled(&token) => None,
+    };
+    if game.is_some() {
+        let _ = tokio::fs::remove_dir_all(directory.join(GAME_DIRECTORY)).await;
     }
+    let Some(results) = results else {
+        if let Some(job) = jobs.get(&token).await {
+            for target in job.targets_in(JobState::Cancelled) {
+                metrics::get().build(target, "cancelled");
+            }
+        }
+        reporter.cancel().await;
+        return;
+    };
 
-    let mut response = ArtifactResponse::new(token);
-    for filepath in results.clone() {
-        response.add_file(filepath);
+    let mut response = ArtifactResponse::new(token).with_version(metadata.lovepotion_version);
+    for (filepath, kind) in results {
+        if let Err(e) = response.add_file(&directory, filepath, kind).await {
+            reporter.error(&format!("Could not record artifact: {e}"));
+        }
     }
+    reporter.finish(&response).await;
+    jobs.finish(&token, response).await;
+}
+
+/// Parses the request's metadata and settles which LÖVE Potion release of
+/// `set` it builds with.
+fn read_metadata(config: &str, set: &ResourceSet) -> Result<Metadata, ApiError> {
+    let mut metadata = Metadata::from_json(config)?;
+    metadata.lovepotion_version = set.resolve(metadata.lovepotion_version.as_deref())?;
+    Ok(metadata)
+}
 
-    match results.len() {
-        0 => Err(Status::BadRequest),
-        _ => response.json().map_err(|_| Status::InternalServerError),
+async fn read_icon(icon: &Option<TempFile<'_>>, set: &ResourceSet) -> Result<Vec<u8>, ApiError> {
+    match icon {
+        Some(icon) if icon.len() > 0 => {
+            let bytes = icon.read_bytes().await.map_err(ApiError::internal)?;
+            let valid = tokio::task::spawn_blocking(move || Icon::is_valid(&bytes).map(|()| bytes));
+            Ok(valid.await.map_err(ApiError::internal)??)
+        }
+        _ => tokio::fs::read(set.icon())
+            .await
+            .map_err(ApiError::internal),
     }
 }
+
+/// What `/compile` and `/bundle` upload to start a build.
+pub struct BuildUpload<'a, 'f> {
+    pub config: &'a str,
+    pub icon: &'a Option<TempFile<'f>>,
+    pub game: Option<&'a TempFile<'f>>,
+    pub limits: Limits,
+}
+
+impl BuildUpload<'_, '_> {
+    /// Bytes of the upload, as received.
+    fn len(&self) -> u64 {
+        let files = self.icon.iter().chain(self.game).map(TempFile::len);
+        self.config.len() as u64 + files.sum::<u64>()
+    }
+}
+
+/// Reads an uploaded build and starts it as a background job, answering
+/// with the job.
+pub async fn start_build(
+    upload: BuildUpload<'_, '_>,
+    uploads: &Uploads<'_>,
+    queue: Queue,
+    jobs: &Jobs,
+    events: &Events,
+) -> Result<String, ApiError> {
+    uploads.record(upload.len());
+
+    // The build runs against the resources its release was resolved in.
+    let set = resources::current();
+    let metadata = read_metadata(upload.config, &set)?;
+    let icon_bytes = read_icon(upload.icon, &set).await?;
+
+    let limits = upload.limits;
+    let game = match upload.game {
+        Some(game) => {
+            let bytes = game.read_bytes().await.map_err(ApiError::internal)?;
+            Archive::is_valid(&bytes, limits)?;
+            Some(GameUpload { bytes, limits })
+        }
+        None => None,
+    };
+
+    let base_dir = artifacts_dir().map_err(ApiError::internal)?;
+
+    let token = Uuid::new_v4();
+    let directory = base_dir.join(token.to_string());
+    if let Err(e) = tokio::fs::create_dir_all(&directory).await {
+        error!("Could not generate directory: {e}");
+        return Err(ApiError::internal(e).with_token(token));
+    }
+
+    let job = jobs.create(token, &metadata.targets).await;
+    let reporter = events.reporter(token);
+    tokio::spawn(resources::pinned(
+        set,
+        run_job(
+            jobs.clone(),
+            queue,
+            reporter,
+            directory,
+            metadata,
+            icon_bytes,
+            game,
+        ),
+    ));
+
+    job.json()
+        .map_err(|e| ApiError::internal(e).with_token(token))
+}
+
+#[post("/compile", data = "<form>")]
+pub async fn compile(
+    form: Form<CompileRequest<'_>>,
+    client: Client,
+    uploads: Uploads<'_>,
+    pool: &State<Pool>,
+    jobs: &State<Jobs>,
+    events: &State<Events>,
+    config: &State<Config>,
+) -> Result<String, ApiError> {
+    let upload = BuildUpload {
+        config: &form.config,
+        icon: &form.icon,
+        game: form.game.as_ref().filter(|game| game.len() > 0),
+        limits: config.archive.limits(),
+    };
+    start_build(upload, &uploads, pool.queue(&client), jobs, events).await
+}
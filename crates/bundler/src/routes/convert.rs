@@ -1,15 +1,27 @@
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
 
 use rocket::{
+    State,
     form::{Form, FromForm},
     fs::TempFile,
     futures::future::join_all,
     http::Status,
-    tokio,
+    tokio::{self, select},
 };
 use uuid::Uuid;
 
-use crate::{response::ArtifactResponse, routes::artifacts_dir, tempfile::TempFileExt};
+use crate::{
+    auth::Uploads,
+    client::Client,
+    error::ApiError,
+    events::{Events, Reporter},
+    jobs::{JobState, Jobs},
+    metrics,
+    pool::{Pool, Queue},
+    response::{ArtifactKind, ArtifactResponse},
+    routes::artifacts_dir,
+    tempfile::TempFileExt,
+};
 use asset::{font::Font, image::Image, process::Process};
 
 #[derive(FromForm)]
@@ -18,73 +30,214 @@ pub struct AssetUpload<'f> {
     paths: Vec<String>,
 }
 
-#[post("/convert", format = "multipart/form-data", data = "<form>")]
-pub async fn convert(form: Form<AssetUpload<'_>>) -> Result<String, Status> {
-    if form.files.is_empty() || form.paths.is_empty() {
-        return Err(Status::BadRequest);
-    }
+/// An uploaded file, read before the conversion starts, and where it goes.
+struct Upload {
+    /// The file's `path` and name, naming it in the job.
+    key: String,
+    path: String,
+    name: String,
+    bytes: Vec<u8>,
+}
 
-    if form.files.len() != form.paths.len() {
-        return Err(Status::BadRequest);
+pub fn processor_for(bytes: &[u8]) -> Option<(Box<dyn Process>, ArtifactKind)> {
+    if Image::is_valid(bytes).is_ok() {
+        Some((Box::new(Image {}), ArtifactKind::Texture))
+    } else if Font::is_valid(bytes).is_ok() {
+        Some((Box::new(Font {}), ArtifactKind::Font))
+    } else {
+        None
     }
+}
 
-    let base_dir = artifacts_dir().map_err(|_| Status::InternalServerError)?;
-
-    let token = Uuid::new_v4();
-    let directory = base_dir.join(token.to_string());
-    if let Err(e) = tokio::fs::create_dir_all(&directory).await {
-        error!("Could not generate directory: {e}");
-        return Err(Status::InternalServerError);
+async fn convert_file(
+    directory: PathBuf,
+    upload: Upload,
+    queue: &Queue,
+    jobs: &Jobs,
+    reporter: &Reporter,
+) -> Result<(PathBuf, ArtifactKind), ApiError> {
+    let ticket = queue.enqueue();
+    let token = reporter.token();
+    let Upload {
+        key,
+        path,
+        name,
+        bytes,
+    } = upload;
+    let filepath = Path::new(&name);
+
+    let (asset, bytes) = tokio::task::spawn_blocking(move || (processor_for(&bytes), bytes))
+        .await
+        .map_err(ApiError::internal)?;
+    reporter.validated(&key, asset.is_some());
+
+    let Some((asset, kind)) = asset else {
+        return Err(ApiError::bad_request(
+            "unsupported_asset",
+            "expected a PNG or JPEG image, or a TrueType or OpenType font",
+        )
+        .with_file(&key));
+    };
+
+    let file_dir = directory.join(&path);
+    tokio::fs::create_dir_all(&file_dir)
+        .await
+        .map_err(ApiError::internal)?;
+
+    let output_path = file_dir.join(&name);
+    if let Err(e) = tokio::fs::write(&output_path, &bytes).await {
+        reporter.error(&format!("Could not write file '{key}': {e}"));
+        return Err(ApiError::internal(e).with_file(&key));
     }
+    let _permit = ticket
+        .wait(|position| {
+            reporter.queued(&key, position);
+            jobs.set_position(&token, &key, position)
+        })
+        .await;
+    jobs.set_state(&token, &key, JobState::Running).await;
+    match asset.process(&file_dir, filepath, reporter).await {
+        Ok(filepath) => {
+            metrics::get().converted(kind);
+            let path = filepath.strip_prefix(&directory).unwrap_or(&filepath);
+            Ok((path.to_owned(), kind))
+        }
+        Err(e) => {
+            reporter.error(&format!("Could not convert '{key}': {e}"));
+            Err(ApiError::from(e).with_file(&key))
+        }
+    }
+}
 
-    let form_data = form.files.iter().zip(form.paths.iter());
-    let tasks = form_data.map(|(file, path)| {
+async fn run_conversion(
+    jobs: &Jobs,
+    queue: Queue,
+    reporter: Reporter,
+    directory: PathBuf,
+    uploads: Vec<Upload>,
+) -> Result<ArtifactResponse, ApiError> {
+    let token = reporter.token();
+    let tasks = uploads.into_iter().map(|upload| {
         let directory = directory.clone();
+        let (queue, reporter) = (&queue, &reporter);
         async move {
-            if file.len() == 0 {
-                return None;
+            let key = upload.key.clone();
+            let result = convert_file(directory, upload, queue, jobs, reporter).await;
+            match &result {
+                Ok(_) => jobs.set_state(&token, &key, JobState::Succeeded).await,
+                Err(e) => jobs.fail(&token, &key, e.clone()).await,
             }
+            result
+        }
+    });
+
+    let (results, errors): (Vec<_>, Vec<_>) = select! {
+        results = join_all(tasks) => results.into_iter().partition(Result::is_ok),
+        _ = jobs.cancelled(&token) => {
+            reporter.cancel().await;
+            return Err(ApiError::new(Status::Conflict, "cancelled", "the job was cancelled"));
+        }
+    };
+
+    let mut response = ArtifactResponse::new(token);
+    for (filepath, kind) in results.into_iter().flatten() {
+        if let Err(e) = response.add_file(&directory, filepath, kind).await {
+            reporter.error(&format!("Could not record artifact: {e}"));
+        }
+    }
+    reporter.finish(&response).await;
+    jobs.finish(&token, response.clone()).await;
 
-            let filepath = Path::new(file.name()?);
-            let bytes = file.read_bytes().await.ok()?;
+    if !response.is_empty() {
+        return Ok(response);
+    }
+    match errors.into_iter().find_map(Result::err) {
+        Some(error) => Err(error),
+        None => Err(ApiError::internal("no artifacts were produced")),
+    }
+}
 
-            let file_dir = directory.join(path);
-            tokio::fs::create_dir_all(&file_dir).await.ok()?;
+#[post("/convert", format = "multipart/form-data", data = "<form>")]
+pub async fn convert(
+    form: Form<AssetUpload<'_>>,
+    client: Client,
+    uploads: Uploads<'_>,
+    pool: &State<Pool>,
+    jobs: &State<Jobs>,
+    events: &State<Events>,
+) -> Result<String, ApiError> {
+    let paths = form.paths.iter().map(|path| path.len() as u64);
+    uploads.record(form.files.iter().map(TempFile::len).chain(paths).sum());
+
+    if form.files.is_empty() {
+        return Err(
+            ApiError::bad_request("missing_field", "no files were uploaded").with_field("files"),
+        );
+    }
 
-            let output_path = file_dir.join(file.name()?);
-            if let Err(e) = tokio::fs::write(&output_path, &bytes).await {
-                error!("Could not write file '{filepath:?}': {e}");
-                return None;
-            }
+    if form.files.len() != form.paths.len() {
+        return Err(ApiError::bad_request(
+            "mismatched_fields",
+            "every file needs exactly one path",
+        )
+        .with_field("paths"));
+    }
 
-            let asset: Box<dyn Process + Send> = if Image::is_valid(&bytes).is_ok() {
-                Box::new(Image {})
-            } else if Font::is_valid(&bytes).is_ok() {
-                Box::new(Font {})
-            } else {
-                return None;
-            };
-
-            let result = asset.process(&file_dir, &filepath);
-            if let Err(result) = result {
-                println!("{result:?}");
-            } else if let Ok(filepath) = result {
-                let path = filepath.strip_prefix(directory).ok()?;
-                return Some(path.to_owned());
-            }
-            None
+    let mut uploads: Vec<Upload> = Vec::with_capacity(form.files.len());
+    for (file, path) in form.files.iter().zip(form.paths.iter()) {
+        let Some(name) = file.name() else {
+            return Err(
+                ApiError::bad_request("missing_field", "file has no name").with_field("files")
+            );
+        };
+        if !Path::new(path)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+        {
+            return Err(ApiError::bad_request(
+                "invalid_path",
+                "paths must be relative and stay inside the artifact",
+            )
+            .with_field("paths"));
         }
-    });
+        let key = Path::new(path).join(name).to_string_lossy().into_owned();
+        if file.len() == 0 {
+            return Err(ApiError::bad_request("empty_file", "file is empty").with_file(&key));
+        }
+        if uploads.iter().any(|upload| upload.key == key) {
+            return Err(
+                ApiError::bad_request("duplicate_file", "file was uploaded twice").with_file(&key),
+            );
+        }
+        let bytes = file.read_bytes().await.map_err(ApiError::internal)?;
+        uploads.push(Upload {
+            key,
+            path: path.clone(),
+            name: name.to_string(),
+            bytes,
+        });
+    }
 
-    let results: Vec<_> = join_all(tasks).await.into_iter().flatten().collect();
+    let base_dir = artifacts_dir().map_err(ApiError::internal)?;
 
-    let mut response = ArtifactResponse::new(token);
-    for filepath in results.clone() {
-        response.add_file(filepath);
+    let token = Uuid::new_v4();
+    let directory = base_dir.join(token.to_string());
+    if let Err(e) = tokio::fs::create_dir_all(&directory).await {
+        error!("Could not generate directory: {e}");
+        return Err(ApiError::internal(e).with_token(token));
     }
 
-    match results.len() {
-        0 => Err(Status::BadRequest),
-        _ => response.json().map_err(|_| Status::InternalServerError),
-    }
+    // The conversion is recorded as a job too, so that it can be followed
+    // on /jobs and cancelled while the request waits for it.
+    let keys: Vec<String> = uploads.iter().map(|upload| upload.key.clone()).collect();
+    jobs.create(token, &keys).await;
+    let queue = pool.queue(&client);
+    let reporter = events.reporter(token);
+    let response = run_conversion(jobs, queue, reporter, directory, uploads)
+        .await
+        .map_err(|e| e.with_token(token))?;
+
+    response
+        .json()
+        .map_err(|e| ApiError::internal(e).with_token(token))
 }
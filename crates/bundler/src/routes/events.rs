@@ -0,0 +1,49 @@
+use crate::{
+    error::ApiError,
+    events::{BuildEvent, Events},
+    routes::parse_token,
+};
+use rocket::{
+    Shutdown, State,
+    response::stream::{Event, EventStream},
+    tokio::{select, sync::broadcast::error::RecvError},
+};
+
+fn to_event(event: &BuildEvent) -> Event {
+    let data = serde_json::to_string(event).unwrap_or_default();
+    Event::data(data).event(event.name())
+}
+
+#[get("/events/<uuid>")]
+pub fn events(
+    uuid: &str,
+    events: &State<Events>,
+    mut shutdown: Shutdown,
+) -> Result<EventStream![], ApiError> {
+    let token = parse_token(uuid)?;
+    let subscription = events.subscribe(&token).ok_or_else(|| {
+        ApiError::not_found("unknown_token", format!("no events for {token}")).with_token(token)
+    })?;
+
+    Ok(EventStream! {
+        for event in &subscription.history {
+            yield to_event(event);
+        }
+        if let Some(mut receiver) = subscription.receiver {
+            loop {
+                let event = select! {
+                    message = receiver.recv() => match message {
+                        Ok(event) => event,
+                        Err(RecvError::Closed) => break,
+                        Err(RecvError::Lagged(_)) => continue,
+                    },
+                    _ = &mut shutdown => break,
+                };
+                yield to_event(&event);
+                if event.is_final() {
+                    break;
+                }
+            }
+        }
+    })
+}
@@ -1,16 +1,150 @@
-use rocket::tokio::{self, sync::OnceCell};
-use system::cache::CACHE_FILENAME;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
-static DATA: OnceCell<String> = OnceCell::const_new();
+use rocket::{
+    State,
+    http::{ContentType, Status},
+    tokio,
+};
+use serde::Serialize;
+use system::{
+    cache::{AssetCache, AssetEntry},
+    platform::Platform,
+    programs::{self, REQUIRED_PROGRAMS, Support, ToolStatus},
+    resources::{self, Resource},
+};
 
-async fn load_cache() -> String {
-    match tokio::fs::read_to_string(CACHE_FILENAME).await {
-        Ok(content) => content,
-        Err(_) => String::from("OK"),
+use crate::{error::ApiError, pool::Pool, routes::artifacts_dir};
+
+type JsonResponse = (Status, (ContentType, String));
+
+#[derive(Serialize)]
+struct ResourceStatus {
+    resource: Resource,
+    /// Relative to the resources in use, so the report shows no server paths.
+    path: PathBuf,
+    present: bool,
+}
+
+#[derive(Serialize)]
+struct DiskStatus {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    free_bytes: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct QueueStatus {
+    queued: usize,
+    running: usize,
+}
+
+#[derive(Serialize)]
+struct Readiness {
+    ready: bool,
+    tools: BTreeMap<&'static str, ToolStatus>,
+    resources: BTreeMap<String, Vec<ResourceStatus>>,
+    releases: Vec<AssetEntry>,
+    disk: DiskStatus,
+    queue: QueueStatus,
+}
+
+#[cfg(unix)]
+fn free_space(path: &Path) -> Option<u64> {
+    let stats = rustix::fs::statvfs(path).ok()?;
+    Some(stats.f_bavail.saturating_mul(stats.f_frsize))
+}
+
+#[cfg(not(unix))]
+fn free_space(_: &Path) -> Option<u64> {
+    None
+}
+
+fn inspect(directory: PathBuf, queue: QueueStatus) -> Readiness {
+    let detected = programs::detected();
+    let tools: BTreeMap<_, _> = REQUIRED_PROGRAMS
+        .iter()
+        .flat_map(|requirement| requirement.binaries)
+        .map(|binary| {
+            let installed = programs::is_installed(binary);
+            let status = match detected.get(binary) {
+                Some(status) => ToolStatus {
+                    installed,
+                    ..status.clone()
+                },
+                None => ToolStatus {
+                    installed,
+                    version: None,
+                    support: Support::Unknown,
+                },
+            };
+            (*binary, status)
+        })
+        .collect();
+    let current = resources::current();
+    let resources: BTreeMap<_, Vec<_>> = Platform::ALL
+        .iter()
+        .map(|platform| {
+            let statuses = resources::required(platform)
+                .into_iter()
+                .map(|(resource, path)| ResourceStatus {
+                    resource,
+                    present: path.exists(),
+                    path: path
+                        .strip_prefix(current.root())
+                        .unwrap_or(&path)
+                        .to_path_buf(),
+                })
+                .collect();
+            (platform.to_string(), statuses)
+        })
+        .collect();
+    let releases = AssetCache::load()
+        .map(|cache| cache.assets().to_vec())
+        .unwrap_or_default();
+
+    let ready = tools.values().all(ToolStatus::is_usable)
+        && resources.values().flatten().all(|status| status.present);
+    Readiness {
+        ready,
+        tools,
+        resources,
+        releases,
+        disk: DiskStatus {
+            free_bytes: free_space(&directory),
+        },
+        queue,
     }
 }
 
+fn json(status: Status, body: &impl Serialize) -> Result<JsonResponse, ApiError> {
+    let body = serde_json::to_string(body).map_err(ApiError::internal)?;
+    Ok((status, (ContentType::JSON, body)))
+}
+
+#[get("/health/live")]
+pub async fn live() -> Result<JsonResponse, ApiError> {
+    json(Status::Ok, &serde_json::json!({ "status": "ok" }))
+}
+
+#[get("/health/ready")]
+pub async fn ready(pool: &State<Pool>) -> Result<JsonResponse, ApiError> {
+    let directory = artifacts_dir().map_err(ApiError::internal)?;
+    let queue = QueueStatus {
+        queued: pool.queued(),
+        running: pool.running(),
+    };
+    let report = tokio::task::spawn_blocking(move || inspect(directory, queue))
+        .await
+        .map_err(ApiError::internal)?;
+    let status = match report.ready {
+        true => Status::Ok,
+        false => Status::ServiceUnavailable,
+    };
+    json(status, &report)
+}
+
+/// Kept for existing uptime checks, answering like `/health/live`.
 #[get("/health")]
-pub async fn health() -> String {
-    DATA.get_or_init(load_cache).await.to_string()
+pub async fn health() -> Result<JsonResponse, ApiError> {
+    live().await
 }
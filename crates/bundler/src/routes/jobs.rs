@@ -0,0 +1,27 @@
+use rocket::State;
+
+use crate::{error::ApiError, jobs::Jobs, routes::parse_token};
+
+#[get("/jobs/<uuid>")]
+pub async fn job(uuid: &str, jobs: &State<Jobs>) -> Result<String, ApiError> {
+    let token = parse_token(uuid)?;
+    let job = jobs
+        .get(&token)
+        .await
+        .ok_or_else(|| ApiError::not_found("unknown_job", format!("no job for {token}")))
+        .map_err(|e| e.with_token(token))?;
+    job.json()
+        .map_err(|e| ApiError::internal(e).with_token(token))
+}
+
+#[delete("/jobs/<uuid>")]
+pub async fn cancel_job(uuid: &str, jobs: &State<Jobs>) -> Result<String, ApiError> {
+    let token = parse_token(uuid)?;
+    let job = jobs
+        .cancel(&token)
+        .await
+        .ok_or_else(|| ApiError::not_found("unknown_job", format!("no job for {token}")))
+        .map_err(|e| e.with_token(token))?;
+    job.json()
+        .map_err(|e| ApiError::internal(e).with_token(token))
+}
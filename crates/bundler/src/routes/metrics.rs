@@ -0,0 +1,13 @@
+use rocket::{http::ContentType, tokio};
+
+use crate::{error::ApiError, gc, routes::artifacts_dir};
+
+#[get("/metrics")]
+pub async fn metrics() -> Result<(ContentType, String), ApiError> {
+    let directory = artifacts_dir().map_err(ApiError::internal)?;
+    let artifacts_bytes = tokio::task::spawn_blocking(move || gc::directory_size(&directory))
+        .await
+        .map_err(ApiError::internal)?;
+    let content_type = ContentType::new("text", "plain").with_params(("version", "0.0.4"));
+    Ok((content_type, crate::metrics::get().render(artifacts_bytes)))
+}
@@ -1,15 +1,42 @@
 use std::path::PathBuf;
+use std::sync::OnceLock;
 
 use anyhow::Result;
+use uuid::Uuid;
 
+use crate::{config::ArtifactsConfig, error::ApiError};
+
+pub mod admin;
 pub mod artifact;
+pub mod bundle;
 pub mod compile;
 pub mod convert;
+pub mod events;
 pub mod health;
+pub mod jobs;
+pub mod metrics;
+pub mod usage;
+
+static ARTIFACTS_DIRECTORY: OnceLock<PathBuf> = OnceLock::new();
+
+/// Sets the directory from `artifacts.directory`. Only the first call has an effect.
+pub fn set_artifacts_dir(directory: PathBuf) {
+    let _ = ARTIFACTS_DIRECTORY.set(directory);
+}
 
 pub fn artifacts_dir() -> Result<PathBuf> {
-    let current_dir = std::env::current_dir()?;
-    let path = current_dir.join(".artifacts");
+    let directory = ARTIFACTS_DIRECTORY.get_or_init(|| ArtifactsConfig::default().directory);
+    let path = std::env::current_dir()?.join(directory);
     std::fs::create_dir_all(&path)?;
     Ok(path)
 }
+
+pub fn parse_token(uuid: &str) -> Result<Uuid, ApiError> {
+    Uuid::parse_str(uuid).map_err(|e| {
+        ApiError::bad_request("invalid_token", format!("invalid token: {e}")).with_field("uuid")
+    })
+}
+
+pub fn trace_path(token: &Uuid) -> Result<PathBuf> {
+    Ok(artifacts_dir()?.join(format!("{token}.log")))
+}
@@ -0,0 +1,18 @@
+use rocket::{State, http::Status};
+
+use crate::{
+    auth::{ApiKey, ApiKeys},
+    error::ApiError,
+};
+
+#[get("/usage")]
+pub async fn usage(key: ApiKey<'_>, keys: &State<ApiKeys>) -> Result<String, ApiError> {
+    let usage = keys.usage(&key).ok_or_else(|| {
+        ApiError::new(
+            Status::Unauthorized,
+            "invalid_api_key",
+            "the API key is not known",
+        )
+    })?;
+    serde_json::to_string(&usage).map_err(ApiError::internal)
+}
@@ -1,10 +1,71 @@
-use rocket::{Build, Rocket};
+use rocket::{Build, Rocket, fairing::AdHoc};
+use system::tool;
 
+use crate::auth::Auth;
+use crate::config::Config;
 use crate::cors::Cors;
-use crate::routes::{artifact::artifact, compile::compile, convert::convert, health::health};
+use crate::error::default_catcher;
+use crate::events::Events;
+use crate::gc;
+use crate::jobs::Jobs;
+use crate::metrics::RequestMetrics;
+use crate::pool;
+use crate::ratelimit::RateLimit;
+use crate::retention::Downloads;
+use crate::routes::{
+    admin::trigger_sync,
+    artifact::{artifact, artifact_log, artifact_zip},
+    bundle::bundle,
+    compile::compile,
+    convert::convert,
+    events::events,
+    health::{health, live, ready},
+    jobs::{cancel_job, job},
+    metrics::metrics,
+    usage::usage,
+};
+use crate::sync::{self, Syncer};
 
 pub fn rocket() -> Rocket<Build> {
     rocket::build()
-        .mount("/", routes![artifact, compile, convert, health])
+        .mount(
+            "/",
+            routes![
+                artifact,
+                artifact_log,
+                artifact_zip,
+                bundle,
+                compile,
+                convert,
+                events,
+                health,
+                live,
+                ready,
+                job,
+                metrics,
+                cancel_job,
+                trigger_sync,
+                usage
+            ],
+        )
+        .register("/", catchers![default_catcher])
+        .manage(Jobs::default())
+        .manage(Events::default())
+        .manage(Downloads::default())
+        .manage(Syncer::default())
+        .attach(AdHoc::config::<Config>())
+        .attach(RequestMetrics)
         .attach(Cors)
+        .attach(RateLimit)
+        .attach(Auth)
+        .attach(AdHoc::on_liftoff("Tool Timeouts", |rocket| {
+            Box::pin(async move {
+                if let Some(config) = rocket.state::<Config>() {
+                    tool::set_timeouts(config.tools.timeouts());
+                }
+            })
+        }))
+        .attach(pool::fairing())
+        .attach(gc::fairing())
+        .attach(sync::fairing())
 }
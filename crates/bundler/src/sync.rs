@@ -0,0 +1,70 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use rocket::{
+    fairing::AdHoc,
+    tokio::{self, sync::Mutex},
+};
+
+use crate::metrics;
+
+/// Runs resource syncs one at a time, whether scheduled or requested.
+#[derive(Clone, Default)]
+pub struct Syncer {
+    running: Arc<Mutex<()>>,
+}
+
+pub async fn sync() -> Result<()> {
+    system::downloads::sync().await?;
+    metrics::get().synced();
+    Ok(())
+}
+
+impl Syncer {
+    pub async fn run(&self) -> Result<()> {
+        let _running = self.running.lock().await;
+        sync().await
+    }
+
+    /// Starts a sync in the background, unless one is already running.
+    pub fn trigger(&self) -> bool {
+        let Ok(running) = self.running.clone().try_lock_owned() else {
+            return false;
+        };
+        tokio::spawn(async move {
+            if let Err(e) = sync().await {
+                error!("Could not sync resources: {e}");
+            }
+            drop(running);
+        });
+        true
+    }
+}
+
+pub fn fairing() -> AdHoc {
+    AdHoc::on_liftoff("Resource Sync", |rocket| {
+        Box::pin(async move {
+            let interval = system::config::get().sync_interval;
+            let Some(syncer) = rocket.state::<Syncer>().cloned() else {
+                error!("Resource sync is missing managed state");
+                return;
+            };
+            if interval == 0 {
+                return;
+            }
+
+            tokio::spawn(async move {
+                let period = Duration::from_secs(interval);
+                let mut interval =
+                    tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+                loop {
+                    interval.tick().await;
+                    if let Err(e) = syncer.run().await {
+                        error!("Could not sync resources: {e}");
+                    }
+                }
+            });
+        })
+    })
+}
@@ -1,51 +1,261 @@
 use std::collections::HashMap;
-use std::path::Path;
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
 
-use anyhow::Result;
+use anyhow::{Context, Result, bail};
 use chrono::{DateTime, Utc};
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-pub const CACHE_FILENAME: &str = ".cache";
+/// Layout of the cache file written by this version.
+const SCHEMA_VERSION: u64 = 2;
 
-#[derive(Serialize, Deserialize, Default)]
+/// Entry of the first layout, a map keyed by asset name.
+#[derive(Deserialize)]
 struct AssetTimestamp {
     downloaded_at: DateTime<Utc>,
     updated_at: DateTime<Utc>,
 }
 
-#[derive(Serialize, Deserialize, Default)]
+/// A synced release asset and what it left in the resources.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AssetEntry {
+    pub name: String,
+    /// Empty for entries migrated from the first layout, which did not record it.
+    #[serde(default)]
+    pub repository: String,
+    /// Empty for entries migrated from the first layout, which did not record it.
+    #[serde(default)]
+    pub tag: String,
+    pub downloaded_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// Files the asset was extracted to, relative to the resource set.
+    #[serde(default)]
+    pub files: Vec<PathBuf>,
+}
+
+impl AssetEntry {
+    fn is(&self, repository: &str, tag: &str, name: &str) -> bool {
+        self.name == name
+            && (self.repository.is_empty() || self.repository == repository)
+            && (self.tag.is_empty() || self.tag == tag)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    version: u64,
+    assets: Vec<AssetEntry>,
+}
+
+#[derive(Default)]
 pub struct AssetCache {
-    cache: HashMap<String, AssetTimestamp>,
+    assets: Vec<AssetEntry>,
+}
+
+/// Where the cache lives. A relative `cache_file` sits in the resources
+/// directory, so the cache stays with the resources it describes.
+fn path() -> PathBuf {
+    let config = crate::config::get();
+    config.resources_directory.join(&config.cache_file)
+}
+
+/// Opens the file guarding the cache against other bundler processes on the host.
+fn lock_file(path: &Path) -> Result<File> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let lock_path = path.with_extension("lock");
+    OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .with_context(|| format!("could not open {}", lock_path.display()))
+}
+
+/// Brings a cache file of any earlier layout up to the current one.
+fn migrate(value: Value, path: &Path) -> Result<Vec<AssetEntry>> {
+    let version = value.get("version").and_then(Value::as_u64).unwrap_or(1);
+    match version {
+        1 => {
+            info!("Migrating {path:?} from schema version 1");
+            let legacy: HashMap<String, AssetTimestamp> = serde_json::from_value(value)?;
+            let assets = legacy
+                .into_iter()
+                .map(|(key, timestamp)| {
+                    let (tag, name) = key.split_once('/').unwrap_or(("", &key));
+                    AssetEntry {
+                        name: name.to_string(),
+                        repository: String::new(),
+                        tag: tag.to_string(),
+                        downloaded_at: timestamp.downloaded_at,
+                        updated_at: timestamp.updated_at,
+                        files: Vec::new(),
+                    }
+                })
+                .collect();
+            Ok(assets)
+        }
+        SCHEMA_VERSION => Ok(serde_json::from_value::<CacheFile>(value)?.assets),
+        _ => bail!("{path:?} has schema version {version}, newer than {SCHEMA_VERSION}"),
+    }
+}
+
+fn read(path: &Path) -> Result<Vec<AssetEntry>> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let value = serde_json::from_str(&contents)
+        .with_context(|| format!("could not parse {}", path.display()))?;
+    migrate(value, path)
 }
 
 impl AssetCache {
     pub fn load() -> Result<AssetCache> {
-        if !Path::new(CACHE_FILENAME).exists() {
-            return Ok(Self::default());
+        let path = path();
+        let lock = lock_file(&path)?;
+        lock.lock_shared()?;
+        let mut assets = read(&path)?;
+        let legacy = &crate::config::get().cache_file;
+        if assets.is_empty() && legacy.is_relative() && *legacy != path {
+            assets = read(legacy)?;
         }
-        let contents = std::fs::read_to_string(CACHE_FILENAME)?;
-        let cache: HashMap<String, AssetTimestamp> = serde_json::from_str(&contents)?;
-        Ok(Self { cache })
-    }
-
-    pub fn update(&mut self, name: &str, timestamp: DateTime<Utc>) -> Result<()> {
-        self.cache.insert(
-            name.to_string(),
-            AssetTimestamp {
-                downloaded_at: Utc::now(),
-                updated_at: timestamp,
-            },
-        );
-        let contents = serde_json::to_string_pretty(&self.cache)?;
-        std::fs::write(CACHE_FILENAME, contents)?;
-        Ok(())
+        Ok(Self { assets })
     }
 
-    pub fn is_up_to_date(&self, name: &str, timestamp: DateTime<Utc>) -> bool {
-        if let Some(cache) = self.cache.get(name) {
-            cache.updated_at >= timestamp
-        } else {
-            false
+    /// Applies `change` to the cache on disk while holding its lock, so
+    /// concurrent syncs on the host keep each other's entries.
+    pub fn update(change: impl FnOnce(&mut AssetCache)) -> Result<()> {
+        let path = path();
+        let lock = lock_file(&path)?;
+        lock.lock()?;
+        let mut cache = Self {
+            assets: read(&path)?,
+        };
+        let legacy = &crate::config::get().cache_file;
+        let migrating = cache.assets.is_empty() && legacy.is_relative() && *legacy != path;
+        if migrating {
+            cache.assets = read(legacy)?;
+        }
+        change(&mut cache);
+        cache.save(&path)?;
+
+        if migrating && legacy.exists() {
+            match std::fs::remove_file(legacy) {
+                Ok(()) => info!("Moved the asset cache from {legacy:?} to {path:?}"),
+                Err(e) => warn!("Could not remove the old asset cache {legacy:?}: {e}"),
+            }
         }
+        Ok(())
+    }
+
+    /// Writes the cache next to `path` and renames it into place, so a crash
+    /// leaves either the old cache or the new one.
+    fn save(&self, path: &Path) -> Result<()> {
+        let contents = serde_json::to_string_pretty(&CacheFile {
+            version: SCHEMA_VERSION,
+            assets: self.assets.clone(),
+        })?;
+        let partial = path.with_extension("tmp");
+        let mut file = File::create(&partial)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(&partial, path)?;
+        Ok(())
+    }
+
+    /// Records `entry`, replacing what was known about the same asset.
+    pub fn record(&mut self, entry: AssetEntry) {
+        self.assets
+            .retain(|asset| !asset.is(&entry.repository, &entry.tag, &entry.name));
+        self.assets.push(entry);
+    }
+
+    /// Forgets the releases of `repository` not tagged with one of `tags`.
+    pub fn retain_tags(&mut self, repository: &str, tags: &[String]) {
+        self.assets
+            .retain(|asset| asset.repository != repository || tags.contains(&asset.tag));
+    }
+
+    /// Every synced asset.
+    pub fn assets(&self) -> &[AssetEntry] {
+        &self.assets
+    }
+
+    /// What is known about the asset `name` of the release `tag` of `repository`.
+    pub fn find(&self, repository: &str, tag: &str, name: &str) -> Option<&AssetEntry> {
+        self.assets
+            .iter()
+            .find(|asset| asset.is(repository, tag, name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const DOWNLOADED: &str = "2024-01-01T00:00:00Z";
+    const UPDATED: &str = "2024-02-01T00:00:00Z";
+
+    #[test]
+    fn migrate_first_layout() {
+        let value = json!({
+            "3.1.0/Nintendo3DS.zip": { "downloaded_at": DOWNLOADED, "updated_at": UPDATED },
+            "icon.png": { "downloaded_at": DOWNLOADED, "updated_at": UPDATED },
+        });
+        let mut assets = migrate(value, Path::new("cache.json")).unwrap();
+        assets.sort_by(|a, b| a.name.cmp(&b.name));
+
+        assert_eq!(assets.len(), 2);
+        assert_eq!(assets[0].name, "Nintendo3DS.zip");
+        assert_eq!(assets[0].tag, "3.1.0");
+        assert!(assets[0].repository.is_empty());
+        assert_eq!(
+            assets[0].downloaded_at.to_rfc3339(),
+            "2024-01-01T00:00:00+00:00"
+        );
+        assert_eq!(
+            assets[0].updated_at.to_rfc3339(),
+            "2024-02-01T00:00:00+00:00"
+        );
+        assert!(assets[0].files.is_empty());
+        assert_eq!(assets[1].name, "icon.png");
+        assert!(assets[1].tag.is_empty());
+    }
+
+    #[test]
+    fn migrate_current_layout() {
+        let value = json!({
+            "version": SCHEMA_VERSION,
+            "assets": [{
+                "name": "Nintendo3DS.zip",
+                "repository": "lovebrew/lovepotion",
+                "tag": "3.1.0",
+                "downloaded_at": DOWNLOADED,
+                "updated_at": UPDATED,
+                "files": ["3.1.0/ctr.elf"],
+            }],
+        });
+        let assets = migrate(value, Path::new("cache.json")).unwrap();
+
+        assert_eq!(assets.len(), 1);
+        assert_eq!(assets[0].repository, "lovebrew/lovepotion");
+        assert_eq!(assets[0].tag, "3.1.0");
+        assert_eq!(assets[0].files, vec![PathBuf::from("3.1.0/ctr.elf")]);
+    }
+
+    #[test]
+    fn migrate_rejects_newer_layout() {
+        let value = json!({ "version": SCHEMA_VERSION + 1, "assets": [] });
+        let Err(error) = migrate(value, Path::new("cache.json")) else {
+            panic!("a newer schema version was accepted");
+        };
+        assert!(error.to_string().contains("newer than"));
     }
 }
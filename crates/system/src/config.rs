@@ -0,0 +1,146 @@
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use anyhow::{Result, bail};
+use serde::Deserialize;
+
+#[derive(Deserialize, Clone)]
+pub struct Repository {
+    pub name: String,
+    /// Single file to take from each release asset instead of the whole archive.
+    #[serde(default)]
+    pub filter: Option<String>,
+    /// Whether each release is kept under its own tag, so builds can pick one.
+    #[serde(default)]
+    pub versioned: bool,
+}
+
+/// Where release assets are synced from.
+#[derive(Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum ReleaseSource {
+    /// The GitHub releases API, or a server compatible with it.
+    GitHub {
+        #[serde(default = "default_api_url")]
+        api_url: String,
+    },
+    /// A directory or HTTP server laid out as `<owner>/<repo>/<tag>/<asset>`,
+    /// with a `latest` file in each repository naming the newest tag.
+    Mirror { location: String },
+}
+
+fn default_api_url() -> String {
+    String::from("https://api.github.com")
+}
+
+impl Default for ReleaseSource {
+    fn default() -> Self {
+        Self::GitHub {
+            api_url: default_api_url(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct Config {
+    /// Directory release assets are extracted into.
+    pub resources_directory: PathBuf,
+    /// File recording each synced release asset, relative to `resources_directory`.
+    pub cache_file: PathBuf,
+    /// Directory under `$DEVKITPRO` that holds the tools.
+    pub search_directory: PathBuf,
+    pub source: ReleaseSource,
+    /// GitHub user or organization owning `repositories`.
+    pub owner: String,
+    pub repositories: Vec<Repository>,
+    /// Tags of the versioned repository kept besides its latest release.
+    pub retained_versions: Vec<String>,
+    /// Whether release assets are synced when the server starts.
+    pub sync_on_launch: bool,
+    /// Seconds between background syncs, `0` to only sync on launch.
+    pub sync_interval: u64,
+    /// Whether tools outside their supported version range stop the server
+    /// from starting. Tools whose version cannot be told only log a warning.
+    pub enforce_tool_versions: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            resources_directory: PathBuf::from("resources"),
+            cache_file: PathBuf::from(".cache"),
+            search_directory: PathBuf::from("tools/bin"),
+            source: ReleaseSource::default(),
+            owner: String::from("lovebrew"),
+            repositories: vec![
+                Repository {
+                    name: String::from("bundler-assets"),
+                    filter: None,
+                    versioned: false,
+                },
+                Repository {
+                    name: String::from("lovepotion"),
+                    filter: Some(String::from("lovepotion.elf")),
+                    versioned: true,
+                },
+            ],
+            retained_versions: Vec::new(),
+            sync_on_launch: true,
+            sync_interval: 6 * 60 * 60,
+            enforce_tool_versions: true,
+        }
+    }
+}
+
+impl Config {
+    pub fn validate(&self) -> Result<()> {
+        if self.resources_directory.as_os_str().is_empty() {
+            bail!("system.resources_directory must not be empty");
+        }
+        if self.cache_file.as_os_str().is_empty() {
+            bail!("system.cache_file must not be empty");
+        }
+        match &self.source {
+            ReleaseSource::GitHub { api_url } => {
+                if !api_url.starts_with("https://") && !api_url.starts_with("http://") {
+                    bail!("system.source.api_url {api_url:?} is not an http(s) URL");
+                }
+            }
+            ReleaseSource::Mirror { location } => {
+                if location.trim().is_empty() {
+                    bail!("system.source.location must not be empty");
+                }
+            }
+        }
+        if self.owner.trim().is_empty() {
+            bail!("system.owner must not be empty");
+        }
+        if self.repositories.is_empty() {
+            bail!("system.repositories must list at least one repository");
+        }
+        if self.repositories.iter().any(|r| r.name.trim().is_empty()) {
+            bail!("system.repositories entries must have a name");
+        }
+        if self.repositories.iter().filter(|r| r.versioned).count() > 1 {
+            bail!("system.repositories may have at most one versioned repository");
+        }
+        for tag in &self.retained_versions {
+            if tag.trim().is_empty() || tag.contains(['/', '\\']) || tag.starts_with('.') {
+                bail!("system.retained_versions has an invalid tag {tag:?}");
+            }
+        }
+        Ok(())
+    }
+}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Installs the configuration read at startup. Only the first call has an effect.
+pub fn init(config: Config) {
+    let _ = CONFIG.set(config);
+}
+
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
@@ -1,91 +1,360 @@
-use std::path::Path;
-use std::{fs::File, io::Read};
+use std::path::{Path, PathBuf};
+use std::{
+    fs::{File, OpenOptions, TryLockError},
+    io::Read,
+};
 
-use anyhow::Result;
-use log::info;
+use anyhow::{Result, bail};
+use chrono::Utc;
+use log::{info, warn};
 use reqwest::Client;
+use sha2::{Digest, Sha256};
 use tempfile::TempDir;
 use zip::ZipArchive;
 
-use crate::cache::AssetCache;
+use crate::{
+    cache::{AssetCache, AssetEntry},
+    config,
+    resources::{self, GENERATION_PREFIX, LATEST_FILE, RELEASE_FILE},
+    source::{self, ReleaseAsset},
+};
 
-struct RepoConfig<'a> {
-    pub name: &'a str,
-    pub filter: Option<&'a str>,
+/// File under the resources directory held locked while a sync runs.
+const SYNC_LOCK_FILE: &str = ".sync.lock";
+
+fn subfolder_for(asset_name: &str) -> &str {
+    if asset_name.contains("3DS") {
+        "ctr"
+    } else if asset_name.contains("Switch") {
+        "hac"
+    } else {
+        "cafe"
+    }
 }
 
-impl RepoConfig<'_> {
-    fn subfolder_for(asset_name: &str) -> &str {
-        if asset_name.contains("3DS") {
-            "ctr"
-        } else if asset_name.contains("Switch") {
-            "hac"
-        } else {
-            "cafe"
+/// Checks a downloaded asset against the size and digest its release lists.
+fn verify(asset: &ReleaseAsset, bytes: &[u8]) -> Result<()> {
+    let size = bytes.len() as u64;
+    if let Some(expected) = asset.size
+        && size != expected
+    {
+        bail!(
+            "{} is {size} bytes, but its release lists {expected}",
+            asset.name
+        );
+    }
+    match &asset.sha256 {
+        Some(expected) => {
+            let digest = format!("{:x}", Sha256::digest(bytes));
+            if digest != *expected {
+                bail!(
+                    "{} has SHA-256 {digest}, but its release lists {expected}",
+                    asset.name
+                );
+            }
         }
+        None => warn!(
+            "{} has no published SHA-256, only its size was checked",
+            asset.name
+        ),
     }
+    Ok(())
 }
 
-pub const RESOURCES_DIRECTORY: &str = "resources";
-
-const REPO_OWNER: &str = "lovebrew";
-const REPOSITORIES: &[RepoConfig] = &[
-    RepoConfig {
-        name: "bundler-assets",
-        filter: None,
-    },
-    RepoConfig {
-        name: "lovepotion",
-        filter: Some("lovepotion.elf"),
-    },
-];
-
-async fn extract_files(file_path: &Path, filter: Option<&str>) -> Result<()> {
+/// Writes `entry` to `path`, flushing it to disk before the resource set can be switched to.
+fn write_entry(entry: &mut impl Read, path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut file = File::create(path)?;
+    std::io::copy(entry, &mut file)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+/// Extracts `file_path` into `resources`, refusing entries that would land
+/// outside it, and returns the files written relative to `resources`.
+/// Reading each entry to the end also checks its CRC.
+fn extract_files(file_path: &Path, filter: Option<&str>, resources: &Path) -> Result<Vec<PathBuf>> {
     info!("Extracting files from {file_path:?}");
     let file = File::open(file_path)?;
     let mut zip_file = ZipArchive::new(file)?;
-    if filter.is_none() {
-        zip_file.extract(RESOURCES_DIRECTORY)?;
-    } else {
+    if let Some(filter_name) = filter {
         let asset_name = file_path.to_string_lossy();
-        let subfolder = RepoConfig::subfolder_for(&asset_name);
-        if let Some(filter_name) = filter {
-            let file_path = format!("{RESOURCES_DIRECTORY}/{subfolder}/{filter_name}");
-            let mut buf = Vec::new();
-            zip_file.by_name(filter_name)?.read_to_end(&mut buf)?;
-            tokio::fs::write(file_path, buf).await?;
+        let relative = Path::new(subfolder_for(&asset_name)).join(filter_name);
+        let mut entry = zip_file.by_name(filter_name)?;
+        write_entry(&mut entry, &resources.join(&relative))?;
+        return Ok(vec![relative]);
+    }
+
+    let mut files = Vec::new();
+    for index in 0..zip_file.len() {
+        let mut entry = zip_file.by_index(index)?;
+        let Some(relative) = entry.enclosed_name() else {
+            bail!(
+                "{file_path:?} has an entry outside its root: {:?}",
+                entry.name()
+            );
+        };
+        if entry.is_symlink() {
+            bail!("{file_path:?} has a symbolic link: {:?}", entry.name());
         }
+        let path = resources.join(&relative);
+        if entry.is_dir() {
+            std::fs::create_dir_all(&path)?;
+        } else {
+            write_entry(&mut entry, &path)?;
+            files.push(relative);
+        }
+    }
+    Ok(files)
+}
+
+fn copy_tree(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let destination = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_tree(&entry.path(), &destination)?;
+        } else {
+            std::fs::copy(entry.path(), destination)?;
+        }
+    }
+    Ok(())
+}
+
+/// Copies the resource set at `from` into `to`, leaving out other generations,
+/// the `CURRENT` pointer and the asset cache kept beside them.
+fn copy_resources(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with('.') || name.starts_with("CURRENT") {
+            continue;
+        }
+        let destination = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_tree(&entry.path(), &destination)?;
+        } else {
+            std::fs::copy(entry.path(), destination)?;
+        }
+    }
+    Ok(())
+}
+
+fn sync_lock_file() -> std::io::Result<File> {
+    let directory = &config::get().resources_directory;
+    std::fs::create_dir_all(directory)?;
+    OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(directory.join(SYNC_LOCK_FILE))
+}
+
+/// Takes the lock that keeps bundler processes on the host from syncing at the same time.
+fn lock_sync() -> std::io::Result<File> {
+    let file = sync_lock_file()?;
+    file.lock()?;
+    Ok(file)
+}
+
+/// Removes resources left behind by an interrupted sync, unless another
+/// bundler process is syncing, in which case its sync removes them.
+pub fn remove_stale_resources() -> std::io::Result<()> {
+    let file = sync_lock_file()?;
+    match file.try_lock() {
+        Ok(()) => resources::remove_stale(),
+        Err(TryLockError::WouldBlock) => {}
+        Err(TryLockError::Error(e)) => return Err(e),
     }
     Ok(())
 }
 
+struct PendingAsset {
+    asset: ReleaseAsset,
+    filter: Option<String>,
+    repository: String,
+    tag: String,
+    /// Directory in the resource set the asset is extracted into.
+    directory: PathBuf,
+}
+
+/// Downloads and verifies every out-of-date release asset into a copy of the
+/// current resources, then switches to that copy once all of them are
+/// extracted. The cache only records assets once the switch succeeded.
+/// Releases of the versioned repository each go in their own `<tag>`
+/// directory, keeping the latest and `retained_versions`.
 pub async fn sync() -> Result<()> {
-    info!("Syncing GitHub resources...");
-    let octocrab = octocrab::instance();
+    info!("Syncing release assets...");
+    let _lock = tokio::task::spawn_blocking(lock_sync).await??;
+    resources::refresh();
+    tokio::task::spawn_blocking(resources::remove_stale).await?;
     let client = Client::new();
     let directory = TempDir::new()?;
-    let mut cache = AssetCache::load()?;
-
-    for repo_config in REPOSITORIES {
-        info!("Fetching assets from {REPO_OWNER}/{}", repo_config.name);
-        let repository = octocrab.repos(REPO_OWNER, repo_config.name);
-        let releases = repository.releases().get_latest().await?;
-
-        for asset in releases.assets {
-            if !cache.is_up_to_date(&asset.name, asset.updated_at) {
-                let response = client.get(asset.browser_download_url).send().await?;
-                let bytes = response.bytes().await?;
-                let file_path = directory.path().join(&asset.name);
-                tokio::fs::write(&file_path, bytes).await?;
-
-                extract_files(&file_path, repo_config.filter).await?;
-                info!("Downloaded and extracted asset: {}", asset.name);
-                cache.update(&asset.name, asset.updated_at)?;
-            } else {
-                info!("Asset {} is up to date.", asset.name);
+    let cache = AssetCache::load()?;
+
+    let config = config::get();
+    let current = resources::current();
+    let mut latest = current.latest().map(str::to_string);
+    let mut kept = None;
+    let mut synced = Vec::new();
+    let mut adopted = Vec::new();
+    let mut pending = Vec::new();
+    for repo_config in &config.repositories {
+        let owner = &config.owner;
+        info!("Fetching assets from {owner}/{}", repo_config.name);
+        let release = source::latest(&client, &repo_config.name).await?;
+        info!("Latest release of {} is {}", repo_config.name, release.tag);
+
+        let mut releases = vec![release];
+        if repo_config.versioned {
+            let mut tags = vec![releases[0].tag.clone()];
+            for tag in &config.retained_versions {
+                if tags.contains(tag) {
+                    continue;
+                }
+                if !resources::is_release(&current.root().join(tag)) {
+                    releases.push(source::tagged(&client, &repo_config.name, tag).await?);
+                }
+                tags.push(tag.clone());
+            }
+            latest = Some(tags[0].clone());
+            synced.push((repo_config.name.clone(), tags.clone()));
+            kept = Some(tags);
+        } else {
+            synced.push((repo_config.name.clone(), vec![releases[0].tag.clone()]));
+        }
+
+        for release in releases {
+            let directory = match repo_config.versioned {
+                true => PathBuf::from(&release.tag),
+                false => PathBuf::new(),
+            };
+            let missing = match repo_config.versioned {
+                true => !resources::is_release(&current.root().join(&directory)),
+                false => !current.root().join(&directory).is_dir(),
+            };
+            for asset in release.assets {
+                let known = cache.find(&repo_config.name, &release.tag, &asset.name);
+                let up_to_date = |entry: &&AssetEntry| {
+                    asset
+                        .updated_at
+                        .is_none_or(|updated_at| entry.updated_at >= updated_at)
+                };
+                if let Some(entry) = known.filter(up_to_date)
+                    && !missing
+                {
+                    info!("Asset {}/{} is up to date.", release.tag, asset.name);
+                    if entry.repository.is_empty() || entry.tag.is_empty() {
+                        adopted.push(AssetEntry {
+                            repository: repo_config.name.clone(),
+                            tag: release.tag.clone(),
+                            ..entry.clone()
+                        });
+                    }
+                    continue;
+                }
+                pending.push(PendingAsset {
+                    asset,
+                    filter: repo_config.filter.clone(),
+                    repository: repo_config.name.clone(),
+                    tag: release.tag.clone(),
+                    directory: directory.clone(),
+                });
             }
         }
     }
-    info!("GitHub assets sync completed successfully.");
+
+    let stale: Vec<_> = match &kept {
+        Some(tags) => current
+            .versions()
+            .into_iter()
+            .filter(|tag| !tags.contains(tag))
+            .collect(),
+        None => Vec::new(),
+    };
+    if pending.is_empty() && stale.is_empty() && latest.as_deref() == current.latest() {
+        if !adopted.is_empty() {
+            AssetCache::update(|cache| adopted.into_iter().for_each(|entry| cache.record(entry)))?;
+        }
+        info!("Release assets are already up to date.");
+        return Ok(());
+    }
+
+    let staging = config.resources_directory.join(format!(
+        "{GENERATION_PREFIX}{}",
+        Utc::now().timestamp_millis()
+    ));
+    tokio::fs::create_dir_all(&staging).await?;
+    let held = resources::hold(&staging)?;
+    let (from, to) = (current.root().to_path_buf(), staging.clone());
+    tokio::task::spawn_blocking(move || copy_resources(&from, &to)).await??;
+
+    let extracted = async {
+        let mut entries = Vec::new();
+        for tag in &stale {
+            tokio::fs::remove_dir_all(staging.join(tag)).await?;
+            info!("Dropped release {tag}, which is no longer retained");
+        }
+        for pending in &pending {
+            let asset = &pending.asset;
+            let bytes = source::download(&client, &asset.location).await?;
+            verify(asset, &bytes)?;
+            let file_path = directory.path().join(&asset.name);
+            tokio::fs::write(&file_path, bytes).await?;
+
+            let destination = staging.join(&pending.directory);
+            let filter = pending.filter.clone();
+            let files = tokio::task::spawn_blocking(move || {
+                extract_files(&file_path, filter.as_deref(), &destination)
+            })
+            .await??;
+            if !pending.directory.as_os_str().is_empty() {
+                let marker = staging.join(&pending.directory).join(RELEASE_FILE);
+                tokio::fs::write(marker, &pending.tag).await?;
+            }
+            info!(
+                "Downloaded and extracted asset: {}/{}",
+                pending.tag, asset.name
+            );
+            entries.push(AssetEntry {
+                name: asset.name.clone(),
+                repository: pending.repository.clone(),
+                tag: pending.tag.clone(),
+                downloaded_at: Utc::now(),
+                updated_at: asset.updated_at.unwrap_or_else(Utc::now),
+                files: files
+                    .into_iter()
+                    .map(|file| pending.directory.join(file))
+                    .collect(),
+            });
+        }
+        if let Some(tag) = &latest {
+            tokio::fs::write(staging.join(LATEST_FILE), tag).await?;
+        }
+        anyhow::Ok(entries)
+    };
+    let entries = match extracted.await {
+        Ok(entries) => entries,
+        Err(e) => {
+            let _ = tokio::fs::remove_dir_all(&staging).await;
+            return Err(e);
+        }
+    };
+
+    resources::install(staging, held)?;
+    AssetCache::update(|cache| {
+        for entry in adopted.into_iter().chain(entries) {
+            cache.record(entry);
+        }
+        for (repository, tags) in &synced {
+            cache.retain_tags(repository, tags);
+        }
+    })?;
+    info!("Release assets sync completed successfully.");
     Ok(())
 }
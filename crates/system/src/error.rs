@@ -0,0 +1,92 @@
+use std::path::PathBuf;
+use std::process::ExitStatus;
+use std::time::Duration;
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("invalid config: {0}")]
+    InvalidConfig(String),
+    #[error("unknown platform: {0}")]
+    UnknownPlatform(String),
+    #[error("invalid image: {0}")]
+    InvalidImage(String),
+    #[error("invalid icon: {0}")]
+    InvalidIcon(String),
+    #[error("invalid font: {0}")]
+    InvalidFont(String),
+    #[error("invalid game archive: {0}")]
+    InvalidArchive(String),
+    #[error("missing resource: {}", .0.display())]
+    MissingResource(PathBuf),
+    #[error("unknown LÖVE Potion version {version}, available: {}", .available.join(", "))]
+    UnknownVersion {
+        version: String,
+        available: Vec<String>,
+    },
+    #[error("could not run {program}{}: {source}", arguments(.args))]
+    Tool {
+        program: String,
+        args: Vec<String>,
+        source: std::io::Error,
+    },
+    #[error("{program} failed with {status}{}", summary(.stderr))]
+    ToolFailed {
+        program: String,
+        args: Vec<String>,
+        status: ExitStatus,
+        stdout: String,
+        stderr: String,
+    },
+    #[error("{program} timed out after {}s", .timeout.as_secs())]
+    TimedOut {
+        program: String,
+        args: Vec<String>,
+        timeout: Duration,
+    },
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+impl Error {
+    /// Stable, machine-readable identifier for this error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::InvalidConfig(_) => "invalid_config",
+            Error::UnknownPlatform(_) => "unknown_target",
+            Error::InvalidImage(_) => "invalid_image",
+            Error::InvalidIcon(_) => "invalid_icon",
+            Error::InvalidFont(_) => "invalid_font",
+            Error::InvalidArchive(_) => "invalid_archive",
+            Error::MissingResource(_) => "missing_resource",
+            Error::UnknownVersion { .. } => "unknown_version",
+            Error::Tool { .. } | Error::ToolFailed { .. } => "tool_failed",
+            Error::TimedOut { .. } => "tool_timeout",
+            Error::Io(_) => "io_error",
+        }
+    }
+
+    /// Arguments of the tool invocation this error comes from.
+    pub fn args(&self) -> Option<&[String]> {
+        match self {
+            Error::Tool { args, .. }
+            | Error::ToolFailed { args, .. }
+            | Error::TimedOut { args, .. } => Some(args),
+            _ => None,
+        }
+    }
+}
+
+fn arguments(args: &[String]) -> String {
+    args.iter().map(|arg| format!(" {arg:?}")).collect()
+}
+
+fn summary(output: &str) -> String {
+    match output.trim() {
+        "" => String::new(),
+        output => format!(": {output}"),
+    }
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
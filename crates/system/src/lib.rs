@@ -1,5 +1,9 @@
 pub mod cache;
+pub mod config;
 pub mod downloads;
+pub mod error;
 pub mod platform;
 pub mod programs;
 pub mod resources;
+pub mod source;
+pub mod tool;
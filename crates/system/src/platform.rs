@@ -3,6 +3,8 @@ use std::{
     str::FromStr,
 };
 
+use crate::error::Error;
+
 #[derive(Clone, Hash, PartialEq, Eq)]
 pub enum Platform {
     Ctr,
@@ -15,7 +17,7 @@ impl Platform {
 }
 
 impl FromStr for Platform {
-    type Err = String;
+    type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         if s.eq_ignore_ascii_case("ctr") {
@@ -25,7 +27,7 @@ impl FromStr for Platform {
         } else if s.eq_ignore_ascii_case("cafe") {
             Ok(Platform::Cafe)
         } else {
-            Err(format!("unknown platform: {s}"))
+            Err(Error::UnknownPlatform(s.to_string()))
         }
     }
 }
@@ -1,50 +1,353 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use std::fs::File;
+use std::io::{Read, Seek};
 use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
 
 use anyhow::{Result, bail};
-use log::{error, info};
-
-const SEARCH_DIRECTORY: &str = "tools/bin";
-const REQUIRED_PROGRAMS: &[(&str, &[&str; 2])] = &[
-    ("tex3ds", &["tex3ds", "mkbcfnt"]),
-    ("3dstools", &["3dsxtool", "smdhtool"]),
-    ("switch-tools", &["nacptool", "elf2nro"]),
-    ("wut-tools", &["elf2rpl", "wuhbtool"]),
+use log::{error, info, warn};
+use serde::{Serialize, Serializer};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    major: u32,
+    minor: u32,
+    patch: u32,
+}
+
+impl Version {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Finds the first dotted version number in a tool's output, such as `v2.3.0`.
+    fn find(text: &str) -> Option<Self> {
+        text.split(|c: char| c.is_whitespace() || matches!(c, '(' | ')' | ',' | ':'))
+            .filter_map(|word| Self::from_str(word.trim_start_matches(['v', 'V'])).ok())
+            .next()
+    }
+}
+
+impl FromStr for Version {
+    type Err = String;
+
+    /// Parses `major.minor[.patch]`, ignoring a package release suffix like `-3`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("{value:?} is not a version");
+        let version = value.split(['-', '+']).next().unwrap_or_default();
+        let parts = version
+            .trim_end_matches('.')
+            .split('.')
+            .map(u32::from_str)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalid())?;
+        match parts[..] {
+            [major, minor] => Ok(Self::new(major, minor, 0)),
+            [major, minor, patch] => Ok(Self::new(major, minor, patch)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl Display for Version {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl Serialize for Version {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// A devkitPro package, the tools it provides and the versions builds are known to work with.
+pub struct Requirement {
+    pub group: &'static str,
+    pub binaries: &'static [&'static str],
+    pub minimum: Version,
+    /// First version that is no longer supported.
+    pub below: Version,
+}
+
+impl Requirement {
+    pub fn supports(&self, version: Version) -> bool {
+        self.minimum <= version && version < self.below
+    }
+}
+
+pub const REQUIRED_PROGRAMS: &[Requirement] = &[
+    Requirement {
+        group: "tex3ds",
+        binaries: &["tex3ds", "mkbcfnt"],
+        minimum: Version::new(2, 0, 0),
+        below: Version::new(3, 0, 0),
+    },
+    Requirement {
+        group: "3dstools",
+        binaries: &["3dsxtool", "smdhtool"],
+        minimum: Version::new(1, 2, 0),
+        below: Version::new(2, 0, 0),
+    },
+    Requirement {
+        group: "switch-tools",
+        binaries: &["nacptool", "elf2nro"],
+        minimum: Version::new(1, 10, 0),
+        below: Version::new(2, 0, 0),
+    },
+    Requirement {
+        group: "wut-tools",
+        binaries: &["elf2rpl", "wuhbtool"],
+        minimum: Version::new(1, 3, 0),
+        below: Version::new(2, 0, 0),
+    },
 ];
 
+/// How long a tool or package manager may take to report a version.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Whether a tool's version is one builds are known to work with.
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Support {
+    Supported,
+    Unsupported,
+    /// The version could not be told.
+    Unknown,
+}
+
+/// What the environment check found for a tool.
+#[derive(Serialize, Clone)]
+pub struct ToolStatus {
+    pub installed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<Version>,
+    pub support: Support,
+}
+
+impl ToolStatus {
+    /// Whether builds may use the tool. Some tools print no version that can
+    /// be told, so only a version known to be unsupported rules one out.
+    pub fn is_usable(&self) -> bool {
+        self.installed && self.support != Support::Unsupported
+    }
+}
+
+static DETECTED: RwLock<BTreeMap<&'static str, ToolStatus>> = RwLock::new(BTreeMap::new());
+
+/// The tools found by the last environment check.
+pub fn detected() -> BTreeMap<&'static str, ToolStatus> {
+    DETECTED
+        .read()
+        .map(|tools| tools.clone())
+        .unwrap_or_default()
+}
+
 pub fn get_binary(binary: &str) -> PathBuf {
     match std::env::var("DEVKITPRO") {
-        Ok(value) => PathBuf::from(value).join(SEARCH_DIRECTORY).join(binary),
+        Ok(value) => PathBuf::from(value)
+            .join(&crate::config::get().search_directory)
+            .join(binary),
         Err(_) => PathBuf::from(binary),
     }
 }
 
-fn check_binary(binary: &str) -> bool {
-    let name = get_binary(binary);
-    if which::which(name).is_err() {
+pub fn is_installed(binary: &str) -> bool {
+    which::which(get_binary(binary)).is_ok()
+}
+
+fn read_all(mut file: File) -> Option<String> {
+    let mut bytes = Vec::new();
+    file.rewind().ok()?;
+    file.read_to_end(&mut bytes).ok()?;
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Runs `program` and returns its stdout then its stderr, or nothing if it
+/// cannot be run or does not exit within [`PROBE_TIMEOUT`].
+fn output_of(program: impl Into<PathBuf>, args: &[&str]) -> Option<String> {
+    let program = program.into();
+    // Output goes to files rather than pipes, so a tool cannot stall on a
+    // full pipe while it is being waited on.
+    let (stdout, stderr) = (tempfile::tempfile().ok()?, tempfile::tempfile().ok()?);
+    let mut child = Command::new(&program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(stdout.try_clone().ok()?)
+        .stderr(stderr.try_clone().ok()?)
+        .spawn()
+        .ok()?;
+    let started = Instant::now();
+    while child.try_wait().ok()?.is_none() {
+        if started.elapsed() >= PROBE_TIMEOUT {
+            warn!(
+                "{} did not answer within {}s",
+                program.display(),
+                PROBE_TIMEOUT.as_secs()
+            );
+            let _ = child.kill();
+            let _ = child.wait();
+            return None;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let mut text = read_all(stdout)?;
+    text.push_str(&read_all(stderr)?);
+    Some(text)
+}
+
+/// Asks the tool for its version, then the package manager for the package's.
+fn probe(binary: &str, group: &str) -> Option<Version> {
+    if let Some(version) = output_of(get_binary(binary), &["--version"]).and_then(|text| {
+        text.lines()
+            .next()
+            .filter(|line| line.contains(binary))
+            .and_then(Version::find)
+    }) {
+        return Some(version);
+    }
+    ["dkp-pacman", "pacman"].iter().find_map(|manager| {
+        let text = output_of(manager, &["-Q", group])?;
+        let line = text.lines().find(|line| line.starts_with(group))?;
+        Version::from_str(line.split_whitespace().nth(1)?).ok()
+    })
+}
+
+fn check_binary(binary: &'static str, requirement: &Requirement) -> ToolStatus {
+    if !is_installed(binary) {
         error!("✘ {binary} is not installed or in PATH.");
-        return false;
+        return ToolStatus {
+            installed: false,
+            version: None,
+            support: Support::Unknown,
+        };
+    }
+    let version = probe(binary, requirement.group);
+    let support = match version {
+        Some(version) if requirement.supports(version) => {
+            info!("✓ Found binary {binary} {version}");
+            Support::Supported
+        }
+        Some(version) => {
+            error!(
+                "✘ {binary} {version} is outside the supported range {} to below {}",
+                requirement.minimum, requirement.below
+            );
+            Support::Unsupported
+        }
+        None => {
+            warn!("? Found binary {binary}, but could not tell its version");
+            Support::Unknown
+        }
+    };
+    ToolStatus {
+        installed: true,
+        version,
+        support,
     }
-    info!("✓ Found binary {binary}");
-    true
 }
 
 pub fn check_environment() -> Result<()> {
     info!("Starting environment check for required programs...");
     let mut missing = Vec::new();
+    let mut unsupported = Vec::new();
+    let mut unknown = Vec::new();
+    let mut detected = BTreeMap::new();
 
-    for &(group, binaries) in REQUIRED_PROGRAMS {
-        info!("Checking group '{group}'");
-        for binary in binaries {
-            if !check_binary(binary) {
-                missing.push(binary);
+    for requirement in REQUIRED_PROGRAMS {
+        info!("Checking group '{}'", requirement.group);
+        for binary in requirement.binaries {
+            let status = check_binary(binary, requirement);
+            match (status.installed, status.support, status.version) {
+                (false, ..) => missing.push(binary),
+                (true, Support::Unsupported, Some(version)) => unsupported.push(format!(
+                    "{binary} {version} (needs {} to below {})",
+                    requirement.minimum, requirement.below
+                )),
+                (true, Support::Unknown, _) => unknown.push(*binary),
+                _ => {}
             }
+            detected.insert(*binary, status);
         }
     }
+    if let Ok(mut tools) = DETECTED.write() {
+        *tools = detected;
+    }
 
     if !missing.is_empty() {
         bail!("Required programs are missing: {missing:?}");
     }
+    let enforce = crate::config::get().enforce_tool_versions;
+    if !unsupported.is_empty() {
+        let message = format!("Unsupported program versions: {}", unsupported.join(", "));
+        if enforce {
+            bail!("{message}");
+        }
+        warn!("{message}");
+    }
+    if !unknown.is_empty() {
+        warn!("Could not tell the versions of: {}", unknown.join(", "));
+    }
 
     info!("All required programs are installed and environment is ready.");
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::Version;
+
+    #[test]
+    fn versions_parse_with_two_or_three_parts() {
+        assert_eq!(Version::from_str("2.3.1"), Ok(Version::new(2, 3, 1)));
+        assert_eq!(Version::from_str("1.10"), Ok(Version::new(1, 10, 0)));
+        assert_eq!(Version::from_str("2.3."), Ok(Version::new(2, 3, 0)));
+    }
+
+    #[test]
+    fn release_suffixes_are_ignored() {
+        assert_eq!(Version::from_str("1.3.0-2"), Ok(Version::new(1, 3, 0)));
+        assert_eq!(Version::from_str("2.0.1+git"), Ok(Version::new(2, 0, 1)));
+    }
+
+    #[test]
+    fn malformed_versions_are_rejected() {
+        for value in ["", "2", "1.2.3.4", "a.b", "1..2", "v1.2"] {
+            assert!(Version::from_str(value).is_err(), "{value:?}");
+        }
+    }
+
+    #[test]
+    fn versions_are_found_in_tool_output() {
+        assert_eq!(
+            Version::find("tex3ds v2.3.0 (built Jan  1 2024)"),
+            Some(Version::new(2, 3, 0))
+        );
+        assert_eq!(
+            Version::find("3dsxtool (devkitPro, version: 1.2.1)"),
+            Some(Version::new(1, 2, 1))
+        );
+        assert_eq!(
+            Version::find("wuhbtool V1.3.2"),
+            Some(Version::new(1, 3, 2))
+        );
+        assert_eq!(Version::find("nacptool 1.10"), Some(Version::new(1, 10, 0)));
+        assert_eq!(Version::find("usage: elf2nro <input> <output>"), None);
+    }
+
+    #[test]
+    fn versions_compare_numerically() {
+        assert!(Version::new(1, 10, 0) > Version::new(1, 9, 9));
+        assert!(Version::new(2, 0, 0) > Version::new(1, 99, 99));
+    }
+}
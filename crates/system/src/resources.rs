@@ -1,42 +1,349 @@
-use std::{collections::HashMap, path::PathBuf, sync::LazyLock};
+use std::fs::{File, OpenOptions, TryLockError};
+use std::future::Future;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::SystemTime;
 
-use crate::{downloads::RESOURCES_DIRECTORY, platform::Platform};
+use log::{error, info, warn};
+use serde::Serialize;
 
-type ResourceMap = HashMap<Resource, PathBuf>;
-type PlatformMap = HashMap<Platform, ResourceMap>;
+use crate::{
+    config,
+    error::{Error, Result},
+    platform::Platform,
+};
 
-#[derive(Hash, PartialEq, Eq)]
+/// File under the resources directory naming the generation in use.
+const CURRENT_FILE: &str = "CURRENT";
+/// Prefix of the directories each synced generation is kept in.
+pub(crate) const GENERATION_PREFIX: &str = ".generation-";
+/// File in a resource set naming the newest release of the versioned repository.
+pub(crate) const LATEST_FILE: &str = "LATEST";
+/// File in each generation that every process using it holds a shared lock on.
+const LOCK_FILE: &str = ".lock";
+/// File marking a directory of a resource set as a kept release, naming its tag.
+pub(crate) const RELEASE_FILE: &str = ".release";
+
+/// Whether `directory` holds a release the sync kept.
+pub(crate) fn is_release(directory: &Path) -> bool {
+    directory.join(RELEASE_FILE).is_file()
+}
+
+#[derive(Serialize, Hash, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
 pub enum Resource {
     ElfBinary,
     DefaultIcon,
+    #[serde(rename = "romfs")]
     RomFS,
 }
 
-fn make_resources(platform: &Platform) -> ResourceMap {
-    let base_dir = PathBuf::from(RESOURCES_DIRECTORY).join(platform.to_string());
+/// Opens the lock file of the generation at `root`, creating it if needed.
+fn lock_file(root: &Path) -> std::io::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(root.join(LOCK_FILE))
+}
+
+/// Takes a shared lock on the generation at `root`, so that no bundler
+/// process on the host removes it while this one may still use it.
+pub(crate) fn hold(root: &Path) -> std::io::Result<File> {
+    let file = lock_file(root)?;
+    file.lock_shared()?;
+    Ok(file)
+}
+
+/// Removes the generation at `root` unless a process still holds it.
+fn remove_unused(root: &Path) {
+    let file = match lock_file(root) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return,
+        Err(e) => {
+            warn!("Could not lock replaced resources {root:?}: {e}");
+            return;
+        }
+    };
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            info!("Keeping replaced resources {root:?}, another process still uses them");
+            return;
+        }
+        Err(TryLockError::Error(e)) => {
+            warn!("Could not lock replaced resources {root:?}: {e}");
+            return;
+        }
+    }
+    match std::fs::remove_dir_all(root) {
+        Ok(()) => info!("Removed replaced resources at {root:?}"),
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => warn!("Could not remove replaced resources {root:?}: {e}"),
+    }
+}
 
-    ResourceMap::from([
-        (Resource::ElfBinary, base_dir.join("lovepotion.elf")),
-        (Resource::RomFS, base_dir.join("files.romfs")),
-    ])
+/// One complete set of synced resources. Once replaced, its directory is
+/// removed as soon as the last build using it lets go, unless another
+/// bundler process still holds it.
+pub struct ResourceSet {
+    root: PathBuf,
+    latest: Option<String>,
+    retired: AtomicBool,
+    /// Shared lock on the generation, `None` for resources synced before generations.
+    lock: Option<File>,
 }
 
-static RESOURCES: LazyLock<PlatformMap> = LazyLock::new(|| {
-    let mut result = PlatformMap::new();
-    for platform in Platform::ALL {
-        result.insert(platform.clone(), make_resources(&platform));
+impl ResourceSet {
+    fn new(root: PathBuf, lock: Option<File>) -> Arc<Self> {
+        let latest = std::fs::read_to_string(root.join(LATEST_FILE))
+            .ok()
+            .map(|tag| tag.trim().to_string())
+            .filter(|tag| !tag.is_empty() && is_release(&root.join(tag)));
+        Arc::new(Self {
+            root,
+            latest,
+            retired: AtomicBool::new(false),
+            lock,
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Tag of the newest release kept, or `None` for resources synced
+    /// before releases were kept per tag.
+    pub fn latest(&self) -> Option<&str> {
+        self.latest.as_deref()
+    }
+
+    /// Tags of every release kept in this set.
+    pub fn versions(&self) -> Vec<String> {
+        let mut versions: Vec<_> = std::fs::read_dir(&self.root)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()))
+            .filter(|entry| is_release(&entry.path()))
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .collect();
+        versions.sort();
+        versions
     }
-    result
-});
+
+    /// Picks the LÖVE Potion release a build uses: `requested` when it is
+    /// kept, otherwise the latest.
+    pub fn resolve(&self, requested: Option<&str>) -> Result<Option<String>> {
+        let Some(version) = requested else {
+            return Ok(self.latest.clone());
+        };
+        let versions = self.versions();
+        match versions.iter().any(|tag| tag == version) {
+            true => Ok(Some(version.to_string())),
+            false => Err(Error::UnknownVersion {
+                version: version.to_string(),
+                available: versions,
+            }),
+        }
+    }
+
+    pub fn icon(&self) -> PathBuf {
+        self.root.join("default.png")
+    }
+}
+
+impl Drop for ResourceSet {
+    fn drop(&mut self) {
+        // Let go of this process's own hold first, so only other processes
+        // can keep the generation around.
+        if self.lock.take().is_some() && self.retired.load(Ordering::Acquire) {
+            remove_unused(&self.root);
+        }
+    }
+}
+
+/// The set in use, and when the `CURRENT` pointer last changed as it was read.
+struct Current {
+    set: Arc<ResourceSet>,
+    modified: Option<SystemTime>,
+}
+
+static CURRENT: RwLock<Option<Current>> = RwLock::new(None);
+
+tokio::task_local! {
+    static PINNED: Arc<ResourceSet>;
+}
+
+fn pointer_modified(directory: &Path) -> Option<SystemTime> {
+    std::fs::metadata(directory.join(CURRENT_FILE))
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// The generation the `CURRENT` pointer names, if it still exists.
+fn pointed_to(directory: &Path) -> Option<String> {
+    std::fs::read_to_string(directory.join(CURRENT_FILE))
+        .ok()
+        .map(|name| name.trim().to_string())
+        .filter(|name| name.starts_with(GENERATION_PREFIX) && directory.join(name).is_dir())
+}
+
+/// Opens the generation the `CURRENT` pointer names, holding it.
+fn open_current(directory: &Path) -> Arc<ResourceSet> {
+    loop {
+        let Some(name) = pointed_to(directory) else {
+            return ResourceSet::new(directory.to_path_buf(), None);
+        };
+        let root = directory.join(name);
+        match hold(&root) {
+            Ok(lock) => return ResourceSet::new(root, Some(lock)),
+            // Replaced and removed since the pointer was read, so read it again.
+            Err(_) if !root.is_dir() => continue,
+            Err(e) => {
+                error!("Could not hold resources {root:?}: {e}");
+                return ResourceSet::new(root, None);
+            }
+        }
+    }
+}
+
+/// Removes the generations other than the current one that no process holds,
+/// such as those left behind by an interrupted sync. Only call this while
+/// holding the sync lock, so that a generation still being synced is kept.
+pub(crate) fn remove_stale() {
+    let directory = &config::get().resources_directory;
+    let current = pointed_to(directory);
+    let Ok(entries) = std::fs::read_dir(directory) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with(GENERATION_PREFIX) && current.as_deref() != Some(name.as_str()) {
+            remove_unused(&entry.path());
+        }
+    }
+}
+
+/// The resource set new builds use. The `CURRENT` pointer is checked on
+/// each call, so generations other bundler processes install are used at once.
+pub fn current() -> Arc<ResourceSet> {
+    let modified = pointer_modified(&config::get().resources_directory);
+    if let Some(current) = CURRENT
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .as_ref()
+        .filter(|current| current.modified == modified)
+    {
+        return current.set.clone();
+    }
+    refresh()
+}
+
+/// Makes the generation at `root`, held by `lock`, current. Builds still
+/// using the previous set keep it until they finish.
+pub(crate) fn install(root: PathBuf, lock: File) -> std::io::Result<()> {
+    let directory = &config::get().resources_directory;
+    let name = root
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let pointer = directory.join(CURRENT_FILE);
+    let partial = pointer.with_extension("tmp");
+    let mut file = File::create(&partial)?;
+    file.write_all(name.as_bytes())?;
+    file.sync_all()?;
+    std::fs::rename(&partial, &pointer)?;
+
+    let previous = CURRENT
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .replace(Current {
+            set: ResourceSet::new(root, Some(lock)),
+            modified: pointer_modified(directory),
+        });
+    if let Some(previous) = previous {
+        previous.set.retired.store(true, Ordering::Release);
+    }
+    info!("Switched resources to {name}");
+    Ok(())
+}
+
+/// Reads the `CURRENT` pointer again, switching to the generation another
+/// bundler process on the host installed, if any.
+pub(crate) fn refresh() -> Arc<ResourceSet> {
+    let directory = &config::get().resources_directory;
+    // Read before the pointer, so a change in between is seen on the next call.
+    let modified = pointer_modified(directory);
+    let mut current = CURRENT.write().unwrap_or_else(PoisonError::into_inner);
+    if let Some(loaded) = current.as_mut() {
+        let root = pointed_to(directory).map(|name| directory.join(name));
+        if root.is_none_or(|root| root == loaded.set.root) {
+            loaded.modified = modified;
+            return loaded.set.clone();
+        }
+    }
+
+    let set = open_current(directory);
+    let previous = current.replace(Current {
+        set: set.clone(),
+        modified,
+    });
+    if let Some(previous) = previous {
+        info!(
+            "Picked up resources {:?} installed by another process",
+            set.root
+        );
+        previous.set.retired.store(true, Ordering::Release);
+    }
+    set
+}
+
+/// Runs `future` with `set`, so it keeps using that set even if a sync
+/// replaces it in the meantime. Take `set` from [`current`] before reading
+/// anything the future relies on, such as the release it resolved.
+pub async fn pinned<F: Future>(set: Arc<ResourceSet>, future: F) -> F::Output {
+    PINNED.scope(set, future).await
+}
+
+fn pinned_set() -> Arc<ResourceSet> {
+    PINNED.try_with(Arc::clone).unwrap_or_else(|_| current())
+}
+
+fn root() -> PathBuf {
+    pinned_set().root.clone()
+}
+
+/// The LÖVE Potion binary of `version`, or of the latest release.
+pub fn fetch_binary(platform: &Platform, version: Option<&str>) -> PathBuf {
+    let set = pinned_set();
+    let base_dir = match version.or(set.latest()) {
+        Some(tag) => set.root.join(tag),
+        None => set.root.clone(),
+    };
+    base_dir.join(platform.to_string()).join("lovepotion.elf")
+}
 
 pub fn fetch_icon() -> PathBuf {
-    let base_dir = PathBuf::from(RESOURCES_DIRECTORY);
-    base_dir.join("default.png")
+    pinned_set().icon()
+}
+
+/// Every resource a build for `platform` reads, with its path.
+pub fn required(platform: &Platform) -> Vec<(Resource, PathBuf)> {
+    vec![
+        (Resource::ElfBinary, fetch(platform, Resource::ElfBinary)),
+        (Resource::RomFS, fetch(platform, Resource::RomFS)),
+        (Resource::DefaultIcon, fetch_icon()),
+    ]
 }
 
 pub fn fetch(platform: &Platform, resource: Resource) -> PathBuf {
-    if let Some(path) = RESOURCES.get(platform).and_then(|map| map.get(&resource)) {
-        return path.to_owned();
+    let base_dir = root().join(platform.to_string());
+    match resource {
+        Resource::ElfBinary => fetch_binary(platform, None),
+        Resource::RomFS => base_dir.join("files.romfs"),
+        Resource::DefaultIcon => fetch_icon(),
     }
-    PathBuf::new()
 }
@@ -0,0 +1,287 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Utc};
+use reqwest::{
+    Client, Url,
+    header::{CONTENT_LENGTH, LAST_MODIFIED},
+};
+
+use crate::config::{self, ReleaseSource};
+
+/// File in a mirrored repository naming its newest tag.
+const LATEST_FILE: &str = "latest";
+/// File in a mirrored release listing its assets, one per line, either by
+/// name or as `sha256sum` output. Required for HTTP mirrors; a local release
+/// directory is listed instead and only reads digests from it.
+const ASSETS_FILE: &str = "assets";
+
+pub enum Location {
+    Url(Url),
+    Path(PathBuf),
+}
+
+pub struct ReleaseAsset {
+    pub name: String,
+    pub location: Location,
+    /// When the asset last changed, if the source tells. An asset without
+    /// one is only downloaded when it is not known yet.
+    pub updated_at: Option<DateTime<Utc>>,
+    /// Size in bytes the release lists for the asset.
+    pub size: Option<u64>,
+    /// Lowercase hex SHA-256 the release lists for the asset.
+    pub sha256: Option<String>,
+}
+
+pub struct Release {
+    pub tag: String,
+    pub assets: Vec<ReleaseAsset>,
+}
+
+/// Finds the latest release of `repository` in the configured source.
+pub async fn latest(client: &Client, repository: &str) -> Result<Release> {
+    find(client, repository, None).await
+}
+
+/// Finds the release of `repository` tagged `tag` in the configured source.
+pub async fn tagged(client: &Client, repository: &str, tag: &str) -> Result<Release> {
+    find(client, repository, Some(tag)).await
+}
+
+async fn find(client: &Client, repository: &str, tag: Option<&str>) -> Result<Release> {
+    let config = config::get();
+    match &config.source {
+        ReleaseSource::GitHub { api_url } => github(api_url, &config.owner, repository, tag).await,
+        ReleaseSource::Mirror { location } => {
+            let base = format!(
+                "{}/{}/{repository}",
+                location.trim_end_matches('/'),
+                config.owner
+            );
+            match base.starts_with("http://") || base.starts_with("https://") {
+                true => http_mirror(client, &base, tag).await,
+                false => {
+                    let base = base.strip_prefix("file://").unwrap_or(&base);
+                    local_mirror(Path::new(base), tag).await
+                }
+            }
+        }
+    }
+}
+
+pub async fn download(client: &Client, location: &Location) -> Result<Vec<u8>> {
+    match location {
+        Location::Url(url) => {
+            let response = client.get(url.clone()).send().await?;
+            Ok(response.error_for_status()?.bytes().await?.to_vec())
+        }
+        Location::Path(path) => Ok(tokio::fs::read(path)
+            .await
+            .with_context(|| format!("could not read {}", path.display()))?),
+    }
+}
+
+async fn github(
+    api_url: &str,
+    owner: &str,
+    repository: &str,
+    tag: Option<&str>,
+) -> Result<Release> {
+    let octocrab = octocrab::Octocrab::builder().base_uri(api_url)?.build()?;
+    let repository = octocrab.repos(owner, repository);
+    let release = match tag {
+        Some(tag) => repository.releases().get_by_tag(tag).await?,
+        None => repository.releases().get_latest().await?,
+    };
+    let assets = release
+        .assets
+        .into_iter()
+        .map(|asset| ReleaseAsset {
+            name: asset.name,
+            location: Location::Url(asset.browser_download_url),
+            updated_at: Some(asset.updated_at),
+            size: u64::try_from(asset.size).ok(),
+            sha256: asset
+                .digest
+                .and_then(|digest| digest.strip_prefix("sha256:").map(str::to_lowercase)),
+        })
+        .collect();
+    // The tag names the release's directory in the resources.
+    Ok(Release {
+        tag: valid_name(&release.tag_name)?,
+        assets,
+    })
+}
+
+/// Checks that a tag or asset name read from a source is a single path component.
+fn valid_name(name: &str) -> Result<String> {
+    let name = name.trim();
+    if name.is_empty()
+        || name.contains(['/', '\\'])
+        || name.contains(char::is_control)
+        || name == "."
+        || name == ".."
+    {
+        bail!("release lists an invalid name {name:?}");
+    }
+    Ok(name.to_string())
+}
+
+/// Reads an assets listing into each asset's name and SHA-256, if given.
+fn parse_listing(listing: &str) -> Result<Vec<(String, Option<String>)>> {
+    let mut assets = Vec::new();
+    for line in listing.lines().filter(|line| !line.trim().is_empty()) {
+        let asset = match line.split_whitespace().collect::<Vec<_>>()[..] {
+            [name] => (valid_name(name)?, None),
+            [digest, name]
+                if digest.len() == 64 && digest.chars().all(|c| c.is_ascii_hexdigit()) =>
+            {
+                let name = valid_name(name.trim_start_matches('*'))?;
+                (name, Some(digest.to_lowercase()))
+            }
+            _ => bail!("mirror lists an invalid asset line {line:?}"),
+        };
+        assets.push(asset);
+    }
+    Ok(assets)
+}
+
+async fn http_mirror(client: &Client, base: &str, tag: Option<&str>) -> Result<Release> {
+    let text = |url: String| async move {
+        let response = client.get(&url).send().await?.error_for_status()?;
+        anyhow::Ok(response.text().await?)
+    };
+    let tag = match tag {
+        Some(tag) => valid_name(tag)?,
+        None => valid_name(&text(format!("{base}/{LATEST_FILE}")).await?)?,
+    };
+    let listing = text(format!("{base}/{tag}/{ASSETS_FILE}")).await?;
+
+    let mut assets = Vec::new();
+    for (name, sha256) in parse_listing(&listing)? {
+        let url = Url::parse(&format!("{base}/{tag}/{name}"))?;
+        let response = client.head(url.clone()).send().await?.error_for_status()?;
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+        };
+        let updated_at = header(LAST_MODIFIED)
+            .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+            .map(|time| time.with_timezone(&Utc));
+        let size = header(CONTENT_LENGTH).and_then(|value| value.parse().ok());
+        assets.push(ReleaseAsset {
+            name,
+            location: Location::Url(url),
+            updated_at,
+            size,
+            sha256,
+        });
+    }
+    Ok(Release { tag, assets })
+}
+
+async fn local_mirror(base: &Path, tag: Option<&str>) -> Result<Release> {
+    let tag = match tag {
+        Some(tag) => valid_name(tag)?,
+        None => {
+            let latest = base.join(LATEST_FILE);
+            let tag = tokio::fs::read_to_string(&latest)
+                .await
+                .with_context(|| format!("could not read {}", latest.display()))?;
+            valid_name(&tag)?
+        }
+    };
+
+    let release = base.join(&tag);
+    let digests: Vec<_> = match tokio::fs::read_to_string(release.join(ASSETS_FILE)).await {
+        Ok(listing) => parse_listing(&listing)?,
+        Err(_) => Vec::new(),
+    };
+
+    let mut assets = Vec::new();
+    let mut entries = tokio::fs::read_dir(&release)
+        .await
+        .with_context(|| format!("could not read {}", release.display()))?;
+    while let Some(entry) = entries.next_entry().await? {
+        let metadata = entry.metadata().await?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !metadata.is_file() || name == ASSETS_FILE {
+            continue;
+        }
+        let sha256 = digests
+            .iter()
+            .find(|(listed, _)| *listed == name)
+            .and_then(|(_, digest)| digest.clone());
+        assets.push(ReleaseAsset {
+            name,
+            location: Location::Path(entry.path()),
+            updated_at: Some(metadata.modified()?.into()),
+            size: Some(metadata.len()),
+            sha256,
+        });
+    }
+    assets.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(Release { tag, assets })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_listing;
+
+    const DIGEST: &str = "9F86D081884C7D659A2FEAA0C55AD015A3BF4F1B2B0B822CD15D6C15B0F00A08";
+
+    #[test]
+    fn names_are_listed_one_per_line() {
+        let assets = parse_listing("Nintendo.3DS.zip\n\n  Nintendo.Switch.zip  \r\n").unwrap();
+        assert_eq!(
+            assets,
+            [
+                (String::from("Nintendo.3DS.zip"), None),
+                (String::from("Nintendo.Switch.zip"), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn sha256sum_lines_carry_lowercase_digests() {
+        let listing = format!("{DIGEST}  Nintendo.3DS.zip\n{DIGEST} *Nintendo.Wii.U.zip\n");
+        let assets = parse_listing(&listing).unwrap();
+        let digest = Some(DIGEST.to_lowercase());
+        assert_eq!(
+            assets,
+            [
+                (String::from("Nintendo.3DS.zip"), digest.clone()),
+                (String::from("Nintendo.Wii.U.zip"), digest),
+            ]
+        );
+    }
+
+    #[test]
+    fn names_leaving_the_release_are_rejected() {
+        for listing in [
+            "../assets.zip",
+            "nested/assets.zip",
+            "..",
+            "C:\\assets.zip",
+            "assets\u{0}.zip",
+        ] {
+            assert!(parse_listing(listing).is_err(), "{listing:?}");
+        }
+        assert!(parse_listing(&format!("{DIGEST}  ../assets.zip")).is_err());
+    }
+
+    #[test]
+    fn malformed_lines_are_rejected() {
+        assert!(parse_listing("abc123  assets.zip").is_err());
+        assert!(parse_listing(&format!("{DIGEST}  assets.zip extra")).is_err());
+        assert!(parse_listing("two names").is_err());
+    }
+
+    #[test]
+    fn an_empty_listing_has_no_assets() {
+        assert!(parse_listing("").unwrap().is_empty());
+        assert!(parse_listing("\n \n").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use std::io::ErrorKind;
+use std::path::Path;
+use std::process::{Output, Stdio};
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use tokio::process::Command;
+
+use crate::error::{Error, Result};
+
+pub struct Invocation {
+    pub program: String,
+    pub args: Vec<String>,
+    started: Instant,
+}
+
+impl Invocation {
+    fn from_command(command: &std::process::Command) -> Self {
+        let program = Path::new(command.get_program());
+        let program = program
+            .file_name()
+            .unwrap_or(program.as_os_str())
+            .to_string_lossy()
+            .to_string();
+        let args = command
+            .get_args()
+            .map(|arg| arg.to_string_lossy().to_string())
+            .collect();
+        Self {
+            program,
+            args,
+            started: Instant::now(),
+        }
+    }
+
+    /// Time since the tool was started.
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+}
+
+impl Display for Invocation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.program)?;
+        for arg in &self.args {
+            write!(f, " {arg:?}")?;
+        }
+        Ok(())
+    }
+}
+
+pub trait Observer: Send + Sync {
+    fn started(&self, _invocation: &Invocation) {}
+    fn finished(&self, _invocation: &Invocation, _output: &std::io::Result<Output>) {}
+}
+
+#[derive(Clone, Default)]
+pub struct Timeouts {
+    /// Applied to every tool without an entry in `tools`.
+    pub default: Option<Duration>,
+    pub tools: BTreeMap<String, Option<Duration>>,
+}
+
+impl Timeouts {
+    fn get(&self, program: &str) -> Option<Duration> {
+        self.tools.get(program).copied().unwrap_or(self.default)
+    }
+}
+
+static TIMEOUTS: RwLock<Timeouts> = RwLock::new(Timeouts {
+    default: None,
+    tools: BTreeMap::new(),
+});
+
+pub fn set_timeouts(timeouts: Timeouts) {
+    if let Ok(mut current) = TIMEOUTS.write() {
+        *current = timeouts;
+    }
+}
+
+fn timeout(program: &str) -> Option<Duration> {
+    TIMEOUTS.read().ok()?.get(program)
+}
+
+/// Runs `command` to completion, killing it if it outlives its timeout or
+/// the returned future is dropped.
+pub async fn run(command: &mut Command, observer: &dyn Observer) -> Result<Output> {
+    let invocation = Invocation::from_command(command.as_std());
+    observer.started(&invocation);
+
+    command.stdin(Stdio::null()).kill_on_drop(true);
+    let output = match timeout(&invocation.program) {
+        Some(limit) => match tokio::time::timeout(limit, command.output()).await {
+            Ok(output) => output,
+            Err(_) => {
+                let error = std::io::Error::new(ErrorKind::TimedOut, "timed out");
+                observer.finished(&invocation, &Err(error));
+                return Err(Error::TimedOut {
+                    program: invocation.program,
+                    args: invocation.args,
+                    timeout: limit,
+                });
+            }
+        },
+        None => command.output().await,
+    };
+    observer.finished(&invocation, &output);
+
+    let output = output.map_err(|source| Error::Tool {
+        program: invocation.program.clone(),
+        args: invocation.args.clone(),
+        source,
+    })?;
+    if !output.status.success() {
+        return Err(Error::ToolFailed {
+            program: invocation.program,
+            args: invocation.args,
+            status: output.status,
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
+    Ok(output)
+}